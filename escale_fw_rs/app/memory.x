@@ -1,9 +1,14 @@
 MEMORY {
     BOOT2 : ORIGIN = 0x10000000, LENGTH = 0x100
-    FLASH : ORIGIN = 0x10000100, LENGTH = 2048K - 0x100
+    FLASH : ORIGIN = 0x10000100, LENGTH = 2048K - 0x100 - 64K
+    /* The key-value store. The last sector is the config of the first firmware. */
+    STORAGE : ORIGIN = 0x10000000 + 2048K - 64K, LENGTH = 60K
     RAM   : ORIGIN = 0x20000000, LENGTH = 256K
 }
 
+__storage_start = ORIGIN(STORAGE);
+__storage_end = ORIGIN(STORAGE) + LENGTH(STORAGE);
+
 EXTERN(BOOT2_FIRMWARE)
 
 SECTIONS {
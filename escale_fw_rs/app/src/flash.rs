@@ -1,8 +1,6 @@
-use core::{
-    mem::{size_of, MaybeUninit},
-    ptr::read_volatile,
-};
+use core::{convert::Infallible, ptr::read_volatile};
 
+use persist::storage::Storage;
 use rp2040_flash::flash;
 
 #[allow(non_upper_case_globals)]
@@ -13,82 +11,90 @@ pub const FLASH_ORIGIN: usize = 0x10000000;
 /// RP2040 supports maximum 16 MiB of QSPI flash memory.
 pub const FLASH_END_MAX: usize = FLASH_ORIGIN + 16 * MiB;
 /// The erasable sector size.
-const FLASH_SECTOR_SIZE: usize = 4096;
+pub const FLASH_SECTOR_SIZE: usize = 4096;
+/// The programmable page size.
+const FLASH_PAGE_SIZE: usize = 256;
 /// The value an erased sector is filled with. This is typically 0xff.
 const FLASH_ERASED_VALUE: u8 = 0xff;
 
-/// The payload type should be `repr(C)` to have a stable layout,
-/// because the flash-stored payload can survive firmware upgrades.
-pub union Flash<T>
-where
-    T: Copy,
-    [(); sector_aligned_size_of::<T>()]:,
-{
-    data: [u8; sector_aligned_size_of::<T>()],
-    value: MaybeUninit<T>,
+/// A sector-aligned flash region.
+pub struct FlashStorage {
+    /// The XIP address of the region.
+    start: usize,
+    sector_count: usize,
 }
 
-impl<T> Default for Flash<T>
-where
-    T: Copy,
-    [(); sector_aligned_size_of::<T>()]:,
-{
-    fn default() -> Self {
+impl FlashStorage {
+    /// # Safety
+    ///
+    /// The region must be reserved for the storage, so it doesn't overlap
+    /// the firmware or anything else stored in the flash.
+    pub unsafe fn new(start: usize, end: usize) -> Self {
+        assert!(start >= FLASH_ORIGIN);
+        assert!(start <= end && end <= FLASH_END_MAX);
+        assert!(is_aligned(start, FLASH_SECTOR_SIZE));
+        assert!(is_aligned(end, FLASH_SECTOR_SIZE));
+
         Self {
-            data: [FLASH_ERASED_VALUE; sector_aligned_size_of::<T>()],
+            start,
+            sector_count: (end - start) / FLASH_SECTOR_SIZE,
         }
     }
-}
 
-impl<T> Flash<T>
-where
-    T: Copy,
-    [(); sector_aligned_size_of::<T>()]:,
-{
-    pub fn new(value: T) -> Self {
-        let mut instance = Self::default();
-        instance.value = MaybeUninit::new(value);
-        instance
+    fn check_bounds(&self, offset: usize, len: usize) {
+        assert!(offset + len <= self.sector_count * FLASH_SECTOR_SIZE);
     }
+}
 
-    pub unsafe fn read(mem_addr: usize) -> Self {
-        assert!(mem_addr >= FLASH_ORIGIN);
-        assert!(mem_addr <= FLASH_END_MAX - size_of::<Self>());
-        // The read address must be sector-aligned, because the write function
-        // only ever allows writing at sector-aligned addresses.
-        assert!(is_aligned(mem_addr, FLASH_SECTOR_SIZE));
+impl Storage for FlashStorage {
+    type Error = Infallible;
 
-        let mut flash_sector = Flash::default();
-        flash_sector.value = unsafe { read_volatile(mem_addr as *const _) };
-        flash_sector
+    fn sector_size(&self) -> usize {
+        FLASH_SECTOR_SIZE
     }
 
-    pub unsafe fn write(&self, mem_addr: usize) {
-        assert!(mem_addr >= FLASH_ORIGIN);
-        assert!(mem_addr <= FLASH_END_MAX - size_of::<Self>());
-        assert!(is_aligned(mem_addr, FLASH_SECTOR_SIZE));
+    fn sector_count(&self) -> usize {
+        self.sector_count
+    }
 
-        let flash_addr = mem_addr - FLASH_ORIGIN;
-        flash::flash_range_erase_and_program(flash_addr as u32, &self.data, true);
+    fn read(&self, offset: usize, buf: &mut [u8]) -> Result<(), Self::Error> {
+        self.check_bounds(offset, buf.len());
+        let mem_addr = self.start + offset;
+        for (i, b) in buf.iter_mut().enumerate() {
+            *b = unsafe { read_volatile((mem_addr + i) as *const u8) };
+        }
+        Ok(())
     }
 
-    pub fn value(&self) -> MaybeUninit<T> {
-        unsafe { self.value }
+    fn erase(&mut self, sector: usize) -> Result<(), Self::Error> {
+        assert!(sector < self.sector_count);
+        let flash_addr = self.start - FLASH_ORIGIN + sector * FLASH_SECTOR_SIZE;
+        cortex_m::interrupt::free(|_cs| unsafe {
+            flash::flash_range_erase(flash_addr as u32, FLASH_SECTOR_SIZE as u32, true)
+        });
+        Ok(())
     }
-}
 
-pub const fn sector_aligned_size_of<T>() -> usize {
-    assert!(FLASH_SECTOR_SIZE.is_power_of_two());
-    ceil_step(size_of::<T>(), FLASH_SECTOR_SIZE)
-}
+    fn program(&mut self, offset: usize, data: &[u8]) -> Result<(), Self::Error> {
+        self.check_bounds(offset, data.len());
+        // Only whole pages can be programmed. Programming the erased value
+        // leaves a byte intact, so the page is padded with it.
+        let mut page = [FLASH_ERASED_VALUE; FLASH_PAGE_SIZE];
+        let (mut offset, mut data) = (offset, data);
+        while !data.is_empty() {
+            let page_offset = offset % FLASH_PAGE_SIZE;
+            let len = data.len().min(FLASH_PAGE_SIZE - page_offset);
+            page.fill(FLASH_ERASED_VALUE);
+            page[page_offset..page_offset + len].copy_from_slice(&data[..len]);
 
-const fn ceil_step(value: usize, step: usize) -> usize {
-    let value_floor = value / step * step;
-    let r = value - value_floor;
-    if r > 0 {
-        value_floor + step
-    } else {
-        value_floor
+            let flash_addr = self.start - FLASH_ORIGIN + offset - page_offset;
+            cortex_m::interrupt::free(|_cs| unsafe {
+                flash::flash_range_program(flash_addr as u32, &page, true)
+            });
+            offset += len;
+            data = &data[len..];
+        }
+        Ok(())
     }
 }
 
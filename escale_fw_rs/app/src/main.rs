@@ -14,10 +14,9 @@ extern crate alloc;
 
 use alloc::rc::Rc;
 use alloc_cortex_m::CortexMHeap;
-use core::mem::size_of;
-use core::{alloc::Layout, cell::RefCell};
+use core::{alloc::Layout, cell::RefCell, ptr::addr_of};
 use embedded_hal::digital::v2::InputPin;
-use flash::{Flash, FLASH_ORIGIN};
+use flash::{FlashStorage, FLASH_ORIGIN, FLASH_SECTOR_SIZE};
 use fugit::RateExtU32;
 use panic_probe as _;
 
@@ -33,16 +32,28 @@ use ssd1306::{
 };
 
 use app_core::{
+    auto_off::AutoOff,
+    capture::{drop_answers, Capture, PendingCapture},
     common::{AppContext, AppMessage, AppTask},
-    dashboard::Dashboard,
+    conf::Conf,
+    dashboard::home_view,
+    dose::captured_dose,
+    flow_rate::FlowRate,
     input_scanner::InputScanner,
-    scale::Scale,
+    profile::Profile,
+    recipe::Recipe,
+    scale::{Scale, Stability, ZeroTracking},
+    scale_fir::{scale_fir, ScaleFir, SCALE_FIR_TAP_COUNT},
+    settings::Settings,
     terminal::Terminal,
+    view_stack::ViewStack,
 };
+use persist::kv::Store;
 use ssd1306_terminal::Ssd1306Terminal;
 use stuff::{
     mq::MessageProcessingStatus,
     run_loop::{FnTask, Schedule, Task, TaskStatus},
+    signal::HampelFilter,
 };
 use uptime::Uptime;
 
@@ -59,30 +70,49 @@ static ALLOCATOR: CortexMHeap = CortexMHeap::empty();
 #[allow(non_upper_case_globals)]
 const MiB: usize = 1024 * 1024;
 const FLASH_END: usize = FLASH_ORIGIN + 2 * MiB;
-const FLASH_CONF_ADDR: usize = FLASH_END - size_of::<Flash<Conf>>();
 
-#[repr(C)]
-#[derive(Copy, Clone)]
-struct Conf {
-    format: u16,
-    scale_unit: f32,
+/// The sector the first firmware stored the configuration in.
+fn baseline_conf() -> FlashStorage {
+    unsafe { FlashStorage::new(FLASH_END - FLASH_SECTOR_SIZE, FLASH_END) }
 }
 
-impl Default for Conf {
-    fn default() -> Self {
-        Self {
-            format: 1,
-            scale_unit: 1.0,
-        }
-    }
+extern "C" {
+    // Defined in `memory.x`
+    static __storage_start: u8;
+    static __storage_end: u8;
+}
+
+fn mount() -> Store<FlashStorage> {
+    let storage = unsafe {
+        FlashStorage::new(
+            addr_of!(__storage_start) as usize,
+            addr_of!(__storage_end) as usize,
+        )
+    };
+    Store::mount(storage).unwrap()
 }
 
-impl Conf {
-    fn is_valid(&self) -> bool {
-        self.format != 0 && !self.format != 0
+type AppScale = Scale<i32, f32, SCALE_FIR_TAP_COUNT, ScaleFir, HampelFilter<i32, 5>>;
+
+const ZERO_TRACKING: ZeroTracking<f32> = ZeroTracking {
+    band: 0.3,
+    rate: 0.01,
+    limit: 10.0,
+};
+
+fn apply_profile(profile: &Profile, scale: &mut AppScale) {
+    scale.set_unit(profile.unit);
+    scale.set_calibration(profile.calibration);
+    if let Some(zero) = profile.zero {
+        scale.set_tare(zero);
     }
 }
 
+fn apply_settings(settings: &Settings, scale: &mut AppScale) {
+    scale.set_filter(scale_fir(settings.filter_strength));
+    scale.set_zero_tracking(settings.auto_tare.then_some(ZERO_TRACKING));
+}
+
 fn init_heap() {
     use core::mem::MaybeUninit;
     const HEAP_SIZE: usize = 128 * 1024;
@@ -153,20 +183,28 @@ fn _main() -> ! {
         let mut terminal = shared_terminal.borrow_mut();
         terminal.clear().unwrap();
     }
-    schedule.push(AppTask::Dashboard(Dashboard::new(
-        shared_terminal,
-        Uptime::get_instant,
-    )));
 
-    let conf = {
-        let conf: Conf = unsafe { Flash::read(FLASH_CONF_ADDR).value().assume_init() };
+    let mut store = mount();
+    let mut conf = Conf::load(&store, &baseline_conf());
+    cx.state.settings = conf.settings;
+    cx.state.active_profile = conf.active_profile;
+    cx.state.profile_names = conf.profiles.map(|p| p.name);
+    cx.state.dose = conf.dose;
+    cx.state.recipes = Recipe::defaults();
+    cx.state.target = conf.target;
+    cx.state.check_limits = conf.check_limits;
+    shared_terminal
+        .borrow_mut()
+        .set_brightness(cx.state.settings.brightness)
+        .unwrap();
 
-        if conf.is_valid() {
-            conf
-        } else {
-            Default::default()
-        }
-    };
+    schedule.push(AppTask::AutoOff(AutoOff::new(Uptime::get_instant, {
+        let terminal = shared_terminal.clone();
+        move |on| terminal.borrow_mut().set_display_on(on).unwrap()
+    })));
+    let mut view_stack = ViewStack::new(shared_terminal.clone());
+    view_stack.push(home_view(cx.state.settings.mode, Uptime::get_instant));
+    schedule.push(AppTask::ViewStack(view_stack));
 
     let i2c1 = I2C::i2c1(
         pac.I2C1,
@@ -184,39 +222,174 @@ fn _main() -> ! {
         &mut uptime,
     )
     .unwrap();
-    let mut scale = Scale::<i32, f32, 20>::default();
-    scale.set_unit(conf.scale_unit);
+    let mut scale = AppScale::new(
+        scale_fir(cx.state.settings.filter_strength),
+        HampelFilter::new(3.0, 64),
+    );
+    apply_profile(&conf.profiles[conf.active_profile], &mut scale);
+    let has_zero = conf.profiles[conf.active_profile].zero.is_some();
+    cx.state.calibration_mass = conf.profiles[conf.active_profile].calibration_mass;
+    scale.set_stability(Stability {
+        window: 10,
+        threshold: 0.5,
+    });
+    apply_settings(&cx.state.settings, &mut scale);
+    // About a second of readouts at 20 SPS
+    let mut flow_rate = FlowRate::<20>::default();
+    let mut pending_capture = PendingCapture::default();
 
     schedule.push(AppTask::Fn(FnTask::new(move |cx: &mut AppContext| {
         if nau7802.data_available().unwrap() {
             let raw = nau7802.read_unchecked().unwrap();
             scale.push(raw);
             if scale.is_filled() {
-                cx.mq.process(|m, _push| match m {
+                let now = Uptime::get_instant();
+                let mut is_cancelled = false;
+                cx.mq.process(|m, push| match m {
                     AppMessage::Tare => {
-                        scale.capture_tare().unwrap();
+                        pending_capture.request(Capture::Tare, now);
+                        MessageProcessingStatus::Processed
+                    }
+                    AppMessage::CaptureZero => {
+                        pending_capture.request(Capture::Zero, now);
+                        MessageProcessingStatus::Processed
+                    }
+                    &AppMessage::Calibrate(mass) => {
+                        pending_capture.request(Capture::Unit(mass), now);
+                        MessageProcessingStatus::Processed
+                    }
+                    &AppMessage::AddCalibrationPoint(mass) => {
+                        pending_capture.request(Capture::Point(mass), now);
+                        MessageProcessingStatus::Processed
+                    }
+                    AppMessage::CancelCapture => {
+                        pending_capture.cancel();
+                        is_cancelled = true;
                         MessageProcessingStatus::Processed
                     }
-                    AppMessage::Calibrate => {
-                        scale.capture_unit(100.0).unwrap();
-                        let conf = Conf {
-                            scale_unit: scale.get_unit(),
-                            ..Default::default()
+                    AppMessage::SaveCalibration => {
+                        let profile = &mut conf.profiles[conf.active_profile];
+                        *profile = Profile {
+                            zero: Some(scale.get_tare()),
+                            unit: scale.get_unit(),
+                            calibration: *scale.get_calibration(),
+                            calibration_mass: cx.state.calibration_mass,
+                            ..*profile
                         };
-                        cortex_m::interrupt::free(|_cs| unsafe {
-                            Flash::new(conf).write(FLASH_CONF_ADDR)
-                        });
+                        conf.save(&mut store).unwrap();
+                        MessageProcessingStatus::Processed
+                    }
+                    AppMessage::RevertCalibration => {
+                        let profile = &conf.profiles[conf.active_profile];
+                        scale.set_unit(profile.unit);
+                        scale.set_calibration(profile.calibration);
+                        cx.state.calibration_mass = profile.calibration_mass;
+                        MessageProcessingStatus::Processed
+                    }
+                    &AppMessage::SelectProfile(index) => {
+                        conf.active_profile = index;
+                        let profile = &conf.profiles[index];
+                        apply_profile(profile, &mut scale);
+                        cx.state.active_profile = index;
+                        cx.state.calibration_mass = profile.calibration_mass;
+                        conf.save(&mut store).unwrap();
+                        if profile.zero.is_none() {
+                            push(AppMessage::Tare);
+                        }
+                        MessageProcessingStatus::Processed
+                    }
+                    &AppMessage::RenameProfile(index, name) => {
+                        conf.profiles[index].name = name;
+                        cx.state.profile_names[index] = name;
+                        conf.save(&mut store).unwrap();
+                        MessageProcessingStatus::Processed
+                    }
+                    AppMessage::ChangeSettings(settings) => {
+                        cx.state.settings = *settings;
+                        apply_settings(settings, &mut scale);
+                        shared_terminal
+                            .borrow_mut()
+                            .set_brightness(settings.brightness)
+                            .unwrap();
+                        MessageProcessingStatus::Processed
+                    }
+                    AppMessage::SaveSettings => {
+                        conf = Conf {
+                            settings: cx.state.settings,
+                            ..conf
+                        };
+                        conf.save(&mut store).unwrap();
+                        MessageProcessingStatus::Processed
+                    }
+                    AppMessage::CaptureDose => {
+                        pending_capture.request(Capture::Dose, now);
+                        MessageProcessingStatus::Processed
+                    }
+                    &AppMessage::SetDose(dose) => {
+                        cx.state.dose = dose;
+                        conf.dose = dose;
+                        conf.save(&mut store).unwrap();
+                        MessageProcessingStatus::Processed
+                    }
+                    &AppMessage::SetTarget(target) => {
+                        cx.state.target = target;
+                        conf.target = target;
+                        conf.save(&mut store).unwrap();
+                        MessageProcessingStatus::Processed
+                    }
+                    &AppMessage::SetCheckLimits(limits) => {
+                        cx.state.check_limits = limits;
+                        cx.state.check_counts = Default::default();
+                        conf.check_limits = limits;
+                        conf.save(&mut store).unwrap();
+                        MessageProcessingStatus::Processed
+                    }
+                    &AppMessage::CountPortion(band) => {
+                        cx.state.check_counts[band as usize] += 1;
                         MessageProcessingStatus::Processed
                     }
                     _ => MessageProcessingStatus::Ignored,
                 });
+                if is_cancelled {
+                    drop_answers(&mut cx.mq);
+                }
+                let given_up = pending_capture.poll(now, |c| match c {
+                    Capture::Tare => scale.capture_tare().map(|()| flow_rate.reset()),
+                    Capture::Zero => scale
+                        .capture_tare()
+                        .map(|()| cx.mq.push(AppMessage::Captured)),
+                    Capture::Unit(mass) => scale.capture_unit(mass).map(|()| {
+                        cx.state.calibration_mass = mass;
+                        cx.mq.push(AppMessage::Captured);
+                    }),
+                    Capture::Point(mass) => scale.capture_point(mass).map(|()| {
+                        cx.state.calibration_mass = mass;
+                        cx.mq.push(AppMessage::Captured);
+                    }),
+                    Capture::Dose => scale.ensure_stable().map(|()| {
+                        let dose = captured_dose(scale.read().unwrap());
+                        cx.state.dose = dose;
+                        conf.dose = dose;
+                        conf.save(&mut store).unwrap();
+                        cx.mq.push(AppMessage::Captured);
+                    }),
+                });
+                if given_up.is_some_and(|c| c.is_answered()) {
+                    cx.mq.push(AppMessage::CaptureFailed);
+                }
                 cx.state.weight = scale.read().unwrap();
+                flow_rate.push(Uptime::get_instant(), cx.state.weight);
+                cx.state.flow_rate = flow_rate.read();
+                cx.state.is_stable = scale.is_stable().unwrap();
+                cx.state.resolution = scale.resolution();
             }
         }
         TaskStatus::Pending
     })));
 
-    cx.mq.push(AppMessage::Tare);
+    if !has_zero {
+        cx.mq.push(AppMessage::Tare);
+    }
 
     loop {
         schedule.run(&mut cx);
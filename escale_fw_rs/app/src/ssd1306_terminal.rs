@@ -1,6 +1,6 @@
 use ssd1306::{
     mode::{TerminalDisplaySize, TerminalMode},
-    prelude::WriteOnlyDataCommand,
+    prelude::{Brightness, WriteOnlyDataCommand},
     Ssd1306,
 };
 
@@ -22,6 +22,26 @@ where
     pub fn new(ssd1306: Ssd1306<DI, SIZE, TerminalMode>) -> Self {
         Self { ssd1306 }
     }
+
+    /// Set the brightness level from 1 (the dimmest) to 5 (the brightest).
+    pub fn set_brightness(&mut self, level: u8) -> core::fmt::Result {
+        let brightness = match level {
+            0 | 1 => Brightness::DIMMEST,
+            2 => Brightness::DIM,
+            3 => Brightness::NORMAL,
+            4 => Brightness::BRIGHT,
+            _ => Brightness::BRIGHTEST,
+        };
+        self.ssd1306
+            .set_brightness(brightness)
+            .map_err(|_| core::fmt::Error)
+    }
+
+    pub fn set_display_on(&mut self, on: bool) -> core::fmt::Result {
+        self.ssd1306
+            .set_display_on(on)
+            .map_err(|_| core::fmt::Error)
+    }
 }
 
 impl<DI, SIZE> core::fmt::Write for Ssd1306Terminal<DI, SIZE>
@@ -0,0 +1,151 @@
+use alloc::boxed::Box;
+use libm::fabsf;
+
+use crate::common::{AppContext, AppMessage, Instant};
+use stuff::{
+    mq::MessageProcessingStatus,
+    run_loop::{Task, TaskStatus},
+};
+
+/// A weight change (in grams) that counts as activity.
+const WEIGHT_ACTIVITY_THRESHOLD: f32 = 1.0;
+
+/// Turns the display off after the timeout set in the settings
+/// if no button is pressed and the weight doesn't change.
+///
+/// The button press that turns the display back on is consumed, so this task
+/// must run before the view stack.
+pub struct AutoOff<'a> {
+    get_instant: fn() -> Instant,
+    set_display_on: Box<dyn FnMut(bool) + 'a>,
+    last_activity: Instant,
+    last_weight: f32,
+    is_off: bool,
+}
+
+impl<'a> AutoOff<'a> {
+    pub fn new<F>(get_instant: fn() -> Instant, set_display_on: F) -> Self
+    where
+        F: FnMut(bool) + 'a,
+    {
+        Self {
+            get_instant,
+            set_display_on: Box::new(set_display_on),
+            last_activity: get_instant(),
+            last_weight: 0.0,
+            is_off: false,
+        }
+    }
+
+    pub fn is_off(&self) -> bool {
+        self.is_off
+    }
+}
+
+impl<'a> Task<AppContext> for AutoOff<'a> {
+    fn run(&mut self, cx: &mut AppContext) -> TaskStatus {
+        let now = (self.get_instant)();
+        let mut is_active = false;
+        cx.mq.process(|m, _| match m {
+            AppMessage::InputEvent(_) => {
+                is_active = true;
+                if self.is_off {
+                    MessageProcessingStatus::Processed
+                } else {
+                    MessageProcessingStatus::Ignored
+                }
+            }
+            _ => MessageProcessingStatus::Ignored,
+        });
+        if fabsf(cx.state.weight - self.last_weight) >= WEIGHT_ACTIVITY_THRESHOLD {
+            self.last_weight = cx.state.weight;
+            is_active = true;
+        }
+        if is_active {
+            self.last_activity = now;
+        }
+
+        let is_off = cx
+            .state
+            .settings
+            .auto_off_timeout()
+            .is_some_and(|timeout| now - self.last_activity >= timeout);
+        if is_off != self.is_off {
+            (self.set_display_on)(!is_off);
+            self.is_off = is_off;
+        }
+        TaskStatus::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::rc::Rc;
+    use core::cell::{Cell, RefCell};
+
+    use super::*;
+    use crate::{
+        button::ButtonEvent,
+        common::{Duration, InputEvent},
+    };
+
+    thread_local! {
+        static NOW: Cell<u64> = const { Cell::new(0) };
+    }
+
+    fn get_instant() -> Instant {
+        Instant::from_ticks(NOW.with(|now| now.get()))
+    }
+
+    fn advance(d: Duration) {
+        NOW.with(|now| now.set(now.get() + d.ticks()));
+    }
+
+    #[test]
+    fn display_is_turned_off_when_idle_and_on_by_a_button() {
+        let mut cx = AppContext::default();
+        cx.state.settings.auto_off_minutes = 1;
+        let display = Rc::new(RefCell::new(true));
+        let mut auto_off = AutoOff::new(get_instant, {
+            let display = display.clone();
+            move |on| *display.borrow_mut() = on
+        });
+
+        advance(Duration::secs(50));
+        auto_off.run(&mut cx);
+        assert!(*display.borrow());
+
+        // A weight change restarts the countdown
+        cx.state.weight = 100.0;
+        auto_off.run(&mut cx);
+        advance(Duration::secs(50));
+        auto_off.run(&mut cx);
+        assert!(*display.borrow());
+        // Small fluctuations don't
+        cx.state.weight = 100.5;
+        advance(Duration::secs(10));
+        auto_off.run(&mut cx);
+        assert!(!*display.borrow());
+        assert!(auto_off.is_off());
+
+        // The button press only turns the display on
+        cx.mq.push(AppMessage::InputEvent(InputEvent::ButtonA(
+            ButtonEvent::Press,
+        )));
+        auto_off.run(&mut cx);
+        assert!(*display.borrow());
+        cx.mq.process(|_, _| panic!("the queue must be empty"));
+
+        // While the display is on, the input is left for the views
+        cx.mq.push(AppMessage::InputEvent(InputEvent::ButtonA(
+            ButtonEvent::Press,
+        )));
+        auto_off.run(&mut cx);
+        let mut n = 0;
+        cx.mq.process(|_, _| {
+            n += 1;
+            MessageProcessingStatus::Processed
+        });
+        assert_eq!(n, 1);
+    }
+}
@@ -9,7 +9,7 @@ pub struct Button<'a> {
     get_instant: Box<dyn Fn() -> Instant + 'a>,
 }
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum ButtonEvent {
     Press,
     LongPress,
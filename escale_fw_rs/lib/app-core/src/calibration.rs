@@ -0,0 +1,443 @@
+use alloc::boxed::Box;
+use libm::fabsf;
+
+use crate::{
+    button::ButtonEvent,
+    calibration_table::CALIBRATION_TABLE_CAPACITY,
+    common::{AppMessage, AppState, InputEvent},
+    message::MessageView,
+    terminal::Terminal,
+    view_stack::{View, ViewContext},
+};
+use stuff::mq::MessageProcessingStatus;
+
+/// Common calibration weights, in grams.
+pub const REFERENCE_MASSES: [f32; 13] = [
+    1.0, 2.0, 5.0, 10.0, 20.0, 50.0, 100.0, 200.0, 250.0, 500.0, 1000.0, 2000.0, 5000.0,
+];
+
+/// The number of reference weights of a calibration, besides the zero point.
+const MAX_WEIGHTS: usize = CALIBRATION_TABLE_CAPACITY - 1;
+
+/// Step-by-step calibration.
+///
+/// A long press on button A proceeds to the next step, on button B cancels
+/// the calibration, also while waiting for the readout to settle. When
+/// placing the reference weight, button A selects the next (heavier) mass
+/// and button B the previous one. Before saving, button A adds another
+/// reference weight for a multi-point calibration.
+pub struct CalibrationWizard {
+    step: Step,
+    is_step_rendered: bool,
+    index: usize,
+    /// The number of reference weights captured.
+    weights: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Step {
+    EmptyPan,
+    CapturingZero,
+    PlaceWeight,
+    CapturingSpan,
+    Confirm,
+}
+
+impl CalibrationWizard {
+    /// Starts with the reference mass nearest to `mass`.
+    pub fn new(mass: f32) -> Self {
+        Self {
+            step: Step::EmptyPan,
+            is_step_rendered: false,
+            index: Self::nearest_index(mass),
+            weights: 0,
+        }
+    }
+
+    fn nearest_index(mass: f32) -> usize {
+        REFERENCE_MASSES
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| fabsf(**a - mass).total_cmp(&fabsf(**b - mass)))
+            .map_or(0, |(i, _)| i)
+    }
+
+    fn mass(&self) -> f32 {
+        REFERENCE_MASSES[self.index]
+    }
+
+    fn can_add_weight(&self) -> bool {
+        self.weights < MAX_WEIGHTS
+    }
+
+    fn go_to(&mut self, step: Step) {
+        self.step = step;
+        self.is_step_rendered = false;
+    }
+
+    fn handle_input(&mut self, e: &InputEvent, cx: &mut ViewContext) {
+        match (self.step, e) {
+            (Step::EmptyPan, InputEvent::ButtonA(ButtonEvent::LongPress)) => {
+                cx.send(AppMessage::CaptureZero);
+                self.go_to(Step::CapturingZero);
+            }
+            (Step::PlaceWeight, InputEvent::ButtonA(ButtonEvent::Press)) => {
+                self.index = (self.index + 1).min(REFERENCE_MASSES.len() - 1);
+            }
+            (Step::PlaceWeight, InputEvent::ButtonB(ButtonEvent::Press)) => {
+                self.index = self.index.saturating_sub(1);
+            }
+            (Step::PlaceWeight, InputEvent::ButtonA(ButtonEvent::LongPress)) => {
+                cx.send(if self.weights == 0 {
+                    AppMessage::Calibrate(self.mass())
+                } else {
+                    AppMessage::AddCalibrationPoint(self.mass())
+                });
+                self.go_to(Step::CapturingSpan);
+            }
+            (Step::Confirm, InputEvent::ButtonA(ButtonEvent::Press)) if self.can_add_weight() => {
+                self.index = (self.index + 1).min(REFERENCE_MASSES.len() - 1);
+                self.go_to(Step::PlaceWeight);
+            }
+            (Step::Confirm, InputEvent::ButtonA(ButtonEvent::LongPress)) => {
+                cx.send(AppMessage::SaveCalibration);
+                cx.replace_view(Box::new(MessageView::new("CALIBRATION\nSAVED")));
+            }
+            (
+                Step::CapturingZero | Step::CapturingSpan,
+                InputEvent::ButtonB(ButtonEvent::LongPress),
+            ) => {
+                cx.send(AppMessage::CancelCapture);
+                self.cancel(cx);
+            }
+            (_, InputEvent::ButtonB(ButtonEvent::LongPress)) => self.cancel(cx),
+            _ => {}
+        }
+    }
+
+    /// Close the wizard, reverting the references captured so far.
+    fn cancel(&mut self, cx: &mut ViewContext) {
+        if self.weights > 0 {
+            cx.send(AppMessage::RevertCalibration);
+        }
+        cx.pop_view();
+    }
+}
+
+impl View for CalibrationWizard {
+    fn handle_message(&mut self, m: &AppMessage, cx: &mut ViewContext) -> MessageProcessingStatus {
+        match m {
+            AppMessage::InputEvent(e) => {
+                self.handle_input(e, cx);
+                MessageProcessingStatus::Processed
+            }
+            AppMessage::Captured => {
+                match self.step {
+                    Step::CapturingZero => self.go_to(Step::PlaceWeight),
+                    Step::CapturingSpan => {
+                        self.weights += 1;
+                        self.go_to(Step::Confirm);
+                    }
+                    _ => {}
+                }
+                MessageProcessingStatus::Processed
+            }
+            AppMessage::CaptureFailed => {
+                match self.step {
+                    Step::CapturingZero => self.go_to(Step::EmptyPan),
+                    Step::CapturingSpan => self.go_to(Step::PlaceWeight),
+                    _ => {}
+                }
+                cx.push_view(Box::new(MessageView::new("UNSTABLE\nTRY AGAIN")));
+                MessageProcessingStatus::Processed
+            }
+            _ => MessageProcessingStatus::Ignored,
+        }
+    }
+
+    fn render(&mut self, terminal: &mut dyn Terminal, state: &AppState) -> core::fmt::Result {
+        if !self.is_step_rendered {
+            terminal.clear()?;
+            terminal.set_position(0, 0)?;
+            match self.step {
+                Step::EmptyPan => terminal.write_str(
+                    "CALIBRATION 1/3\n\nEMPTY THE PAN\n\n\nHOLD A: NEXT\nHOLD B: CANCEL",
+                )?,
+                Step::CapturingZero | Step::CapturingSpan => {
+                    terminal.write_str("CALIBRATION\n\nHOLD STILL...\n\n\n\n\nHOLD B: CANCEL")?
+                }
+                Step::PlaceWeight => terminal.write_str(
+                    "CALIBRATION 2/3\n\nPLACE WEIGHT:\n\n\nA/B: +/-\nHOLD A: NEXT\nHOLD B: CANCEL",
+                )?,
+                Step::Confirm => {
+                    terminal.write_str("CALIBRATION 3/3\n\nWEIGHT:\n\n\n")?;
+                    if self.can_add_weight() {
+                        terminal.write_str("A: ADD WEIGHT")?;
+                    }
+                    terminal.write_str("\nHOLD A: SAVE\nHOLD B: CANCEL")?;
+                }
+            }
+            self.is_step_rendered = true;
+        }
+        match self.step {
+            Step::PlaceWeight => {
+                terminal.set_position(0, 3)?;
+                terminal.write_fmt(format_args!("{:>8.0} g", self.mass()))?;
+            }
+            Step::Confirm => {
+                terminal.set_position(0, 3)?;
+                terminal.write_fmt(format_args!("{:>10.2} g", state.weight))?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn appear(&mut self) {
+        self.is_step_rendered = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::rc::Rc;
+    use core::cell::RefCell;
+
+    use super::*;
+    use crate::{
+        common::AppContext,
+        testing::{input, show, take_messages, FakeTerminal},
+        view_stack::ViewStack,
+    };
+    use stuff::run_loop::{Task, TaskStatus};
+
+    fn wizard(mass: f32) -> (ViewStack, Rc<RefCell<FakeTerminal>>) {
+        show(Box::new(CalibrationWizard::new(mass)))
+    }
+
+    #[test]
+    fn calibration_is_captured_and_saved() {
+        let mut cx = AppContext::default();
+        let (mut w, terminal) = wizard(100.0);
+
+        assert!(matches!(w.run(&mut cx), TaskStatus::Pending));
+        assert_eq!(terminal.borrow().line(2), "EMPTY THE PAN");
+
+        // Short presses don't proceed
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::Press));
+        w.run(&mut cx);
+        assert_eq!(terminal.borrow().line(2), "EMPTY THE PAN");
+
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::LongPress));
+        w.run(&mut cx);
+        assert_eq!(terminal.borrow().line(2), "HOLD STILL...");
+        assert_eq!(terminal.borrow().line(7), "HOLD B: CANCEL");
+        assert_eq!(take_messages(&mut cx), [AppMessage::CaptureZero]);
+
+        // Input other than cancelling is ignored until the scale responds
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::LongPress));
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::Press));
+        w.run(&mut cx);
+        assert_eq!(terminal.borrow().line(2), "HOLD STILL...");
+        assert_eq!(take_messages(&mut cx), []);
+
+        cx.mq.push(AppMessage::Captured);
+        w.run(&mut cx);
+        assert_eq!(terminal.borrow().line(2), "PLACE WEIGHT:");
+        assert_eq!(terminal.borrow().line(3), "     100 g");
+
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::Press));
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::Press));
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::Press));
+        w.run(&mut cx);
+        assert_eq!(terminal.borrow().line(3), "     200 g");
+
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::LongPress));
+        w.run(&mut cx);
+        assert_eq!(terminal.borrow().line(2), "HOLD STILL...");
+        assert_eq!(take_messages(&mut cx), [AppMessage::Calibrate(200.0)]);
+
+        cx.mq.push(AppMessage::Captured);
+        cx.state.weight = 200.01;
+        w.run(&mut cx);
+        assert_eq!(terminal.borrow().line(2), "WEIGHT:");
+        assert_eq!(terminal.borrow().line(3), "    200.01 g");
+
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::LongPress));
+        w.run(&mut cx);
+        assert_eq!(terminal.borrow().line(3), "SAVED");
+        assert_eq!(take_messages(&mut cx), [AppMessage::SaveCalibration]);
+    }
+
+    #[test]
+    fn weights_are_added() {
+        let mut cx = AppContext::default();
+        let (mut w, terminal) = wizard(100.0);
+
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::LongPress));
+        w.run(&mut cx);
+        cx.mq.push(AppMessage::Captured);
+        w.run(&mut cx);
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::LongPress));
+        w.run(&mut cx);
+        cx.mq.push(AppMessage::Captured);
+        w.run(&mut cx);
+        assert_eq!(terminal.borrow().line(5), "A: ADD WEIGHT");
+        assert_eq!(
+            take_messages(&mut cx),
+            [AppMessage::CaptureZero, AppMessage::Calibrate(100.0)]
+        );
+
+        // The next heavier mass is proposed
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::Press));
+        w.run(&mut cx);
+        assert_eq!(terminal.borrow().line(2), "PLACE WEIGHT:");
+        assert_eq!(terminal.borrow().line(3), "     200 g");
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::Press));
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::LongPress));
+        w.run(&mut cx);
+        cx.mq.push(AppMessage::Captured);
+        w.run(&mut cx);
+        assert_eq!(terminal.borrow().line(2), "WEIGHT:");
+        assert_eq!(
+            take_messages(&mut cx),
+            [AppMessage::AddCalibrationPoint(250.0)]
+        );
+
+        // Up to the capacity of the calibration table
+        for _ in 2..MAX_WEIGHTS {
+            input(&mut cx, InputEvent::ButtonA(ButtonEvent::Press));
+            input(&mut cx, InputEvent::ButtonA(ButtonEvent::LongPress));
+            w.run(&mut cx);
+            cx.mq.push(AppMessage::Captured);
+            w.run(&mut cx);
+        }
+        assert_eq!(terminal.borrow().line(5), "");
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::Press));
+        w.run(&mut cx);
+        assert_eq!(terminal.borrow().line(2), "WEIGHT:");
+
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::LongPress));
+        w.run(&mut cx);
+        assert_eq!(
+            take_messages(&mut cx),
+            [
+                AppMessage::AddCalibrationPoint(500.0),
+                AppMessage::AddCalibrationPoint(1000.0),
+                AppMessage::AddCalibrationPoint(2000.0),
+                AppMessage::AddCalibrationPoint(5000.0),
+                AppMessage::AddCalibrationPoint(5000.0),
+                AppMessage::SaveCalibration,
+            ]
+        );
+    }
+
+    #[test]
+    fn calibration_can_be_reverted() {
+        let mut cx = AppContext::default();
+        let (mut w, _) = wizard(100.0);
+
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::LongPress));
+        w.run(&mut cx);
+        cx.mq.push(AppMessage::Captured);
+        w.run(&mut cx);
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::LongPress));
+        w.run(&mut cx);
+        cx.mq.push(AppMessage::Captured);
+        w.run(&mut cx);
+        _ = take_messages(&mut cx);
+
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::LongPress));
+        assert!(matches!(w.run(&mut cx), TaskStatus::Done));
+        assert_eq!(take_messages(&mut cx), [AppMessage::RevertCalibration]);
+    }
+
+    #[test]
+    fn calibration_can_be_cancelled_before_capturing_span() {
+        let mut cx = AppContext::default();
+        let (mut w, _) = wizard(100.0);
+
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::LongPress));
+        w.run(&mut cx);
+        cx.mq.push(AppMessage::Captured);
+        w.run(&mut cx);
+        _ = take_messages(&mut cx);
+
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::LongPress));
+        assert!(matches!(w.run(&mut cx), TaskStatus::Done));
+        assert_eq!(take_messages(&mut cx), []);
+    }
+
+    #[test]
+    fn calibration_can_be_cancelled_while_capturing() {
+        let mut cx = AppContext::default();
+        let (mut w, _) = wizard(100.0);
+
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::LongPress));
+        w.run(&mut cx);
+        _ = take_messages(&mut cx);
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::LongPress));
+        assert!(matches!(w.run(&mut cx), TaskStatus::Done));
+        assert_eq!(take_messages(&mut cx), [AppMessage::CancelCapture]);
+
+        // The references captured so far are reverted
+        let (mut w, _) = wizard(100.0);
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::LongPress));
+        w.run(&mut cx);
+        cx.mq.push(AppMessage::Captured);
+        w.run(&mut cx);
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::LongPress));
+        w.run(&mut cx);
+        cx.mq.push(AppMessage::Captured);
+        w.run(&mut cx);
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::Press));
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::LongPress));
+        w.run(&mut cx);
+        _ = take_messages(&mut cx);
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::LongPress));
+        assert!(matches!(w.run(&mut cx), TaskStatus::Done));
+        assert_eq!(
+            take_messages(&mut cx),
+            [AppMessage::CancelCapture, AppMessage::RevertCalibration]
+        );
+    }
+
+    #[test]
+    fn failed_capture_returns_to_the_step() {
+        let mut cx = AppContext::default();
+        let (mut w, terminal) = wizard(100.0);
+
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::LongPress));
+        w.run(&mut cx);
+        cx.mq.push(AppMessage::Captured);
+        w.run(&mut cx);
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::LongPress));
+        w.run(&mut cx);
+        _ = take_messages(&mut cx);
+
+        cx.mq.push(AppMessage::CaptureFailed);
+        w.run(&mut cx);
+        assert_eq!(terminal.borrow().line(2), "UNSTABLE");
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::Press));
+        w.run(&mut cx);
+        w.run(&mut cx);
+        assert_eq!(terminal.borrow().line(2), "PLACE WEIGHT:");
+        assert_eq!(take_messages(&mut cx), []);
+    }
+
+    #[test]
+    fn unrelated_messages_are_left_in_the_queue() {
+        let mut cx = AppContext::default();
+        let (mut w, _) = wizard(100.0);
+        cx.mq.push(AppMessage::Tare);
+        w.run(&mut cx);
+        assert_eq!(take_messages(&mut cx), [AppMessage::Tare]);
+    }
+
+    #[test]
+    fn initial_mass_is_the_nearest_reference() {
+        assert_eq!(CalibrationWizard::new(0.0).mass(), 1.0);
+        assert_eq!(CalibrationWizard::new(240.0).mass(), 250.0);
+        assert_eq!(CalibrationWizard::new(1e6).mass(), 5000.0);
+    }
+}
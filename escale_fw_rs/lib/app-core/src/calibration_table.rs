@@ -0,0 +1,254 @@
+use num_traits::float::FloatCore;
+
+pub const CALIBRATION_TABLE_CAPACITY: usize = 8;
+
+/// A reference point: the raw readout (net of tare) and the known value.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CalibrationPoint<U> {
+    pub raw: U,
+    pub value: U,
+}
+
+/// Multi-point calibration.
+///
+/// The points are kept sorted by the raw readout. When the values are
+/// strictly monotonic, a readout is mapped piecewise-linearly between
+/// the neighboring points (and extrapolated along the outermost segments).
+/// Otherwise the least-squares line through all the points is used.
+///
+/// The table has a stable layout to be persisted as is.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct CalibrationTable<U> {
+    points: [CalibrationPoint<U>; CALIBRATION_TABLE_CAPACITY],
+    len: u32,
+}
+
+impl<U: FloatCore> PartialEq for CalibrationTable<U> {
+    fn eq(&self, other: &Self) -> bool {
+        self.points() == other.points()
+    }
+}
+
+impl<U: FloatCore> Default for CalibrationTable<U> {
+    fn default() -> Self {
+        Self {
+            points: [CalibrationPoint {
+                raw: U::zero(),
+                value: U::zero(),
+            }; CALIBRATION_TABLE_CAPACITY],
+            len: 0,
+        }
+    }
+}
+
+impl<U: FloatCore> CalibrationTable<U> {
+    pub fn points(&self) -> &[CalibrationPoint<U>] {
+        &self.points[..self.len as usize]
+    }
+
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Insert a point keeping the table sorted.
+    ///
+    /// A point with the same value is replaced.
+    pub fn insert(&mut self, point: CalibrationPoint<U>) -> Result<(), Error> {
+        if !point.raw.is_finite() || !point.value.is_finite() {
+            return Err(Error::NotFinite);
+        }
+        if let Some(i) = self.points().iter().position(|p| p.value == point.value) {
+            self.remove(i);
+        }
+        let len = self.len();
+        if len == CALIBRATION_TABLE_CAPACITY {
+            return Err(Error::Full);
+        }
+        let i = self.points().partition_point(|p| p.raw < point.raw);
+        self.points.copy_within(i..len, i + 1);
+        self.points[i] = point;
+        self.len += 1;
+        Ok(())
+    }
+
+    fn remove(&mut self, i: usize) {
+        let len = self.len();
+        self.points.copy_within(i + 1..len, i);
+        self.len -= 1;
+    }
+
+    /// Check the invariants, e.g. after reading the table from storage.
+    pub fn is_valid(&self) -> bool {
+        self.len() <= CALIBRATION_TABLE_CAPACITY
+            && self
+                .points()
+                .iter()
+                .all(|p| p.raw.is_finite() && p.value.is_finite())
+            && self.points().windows(2).all(|w| w[0].raw <= w[1].raw)
+    }
+
+    /// Map a raw readout (net of tare) to a value.
+    ///
+    /// Returns `None` if the table has fewer than two distinct points.
+    pub fn map(&self, raw: U) -> Option<U> {
+        let points = self.points();
+        if points.len() < 2 {
+            None
+        } else if self.is_monotonic() {
+            let i = points
+                .partition_point(|p| p.raw < raw)
+                .clamp(1, points.len() - 1);
+            Some(interpolate(points[i - 1], points[i], raw))
+        } else {
+            self.fit().map(|(a, b)| a + b * raw)
+        }
+    }
+
+    /// The average number of raw units per unit of value (i.e. the slope
+    /// of the least-squares line inverted).
+    pub fn unit(&self) -> Option<U> {
+        self.fit().map(|(_, b)| U::one() / b)
+    }
+
+    fn is_monotonic(&self) -> bool {
+        let points = self.points();
+        points.windows(2).all(|w| w[0].raw < w[1].raw)
+            && (points.windows(2).all(|w| w[0].value < w[1].value)
+                || points.windows(2).all(|w| w[0].value > w[1].value))
+    }
+
+    /// The least-squares line `value = a + b × raw` as `(a, b)`.
+    fn fit(&self) -> Option<(U, U)> {
+        let points = self.points();
+        if points.len() < 2 {
+            return None;
+        }
+        let n = U::from(points.len()).unwrap();
+        let (sum_raw, sum_value) = points
+            .iter()
+            .fold((U::zero(), U::zero()), |(x, y), p| (x + p.raw, y + p.value));
+        let (mean_raw, mean_value) = (sum_raw / n, sum_value / n);
+        let (sxy, sxx) = points.iter().fold((U::zero(), U::zero()), |(sxy, sxx), p| {
+            let dx = p.raw - mean_raw;
+            (sxy + dx * (p.value - mean_value), sxx + dx * dx)
+        });
+        if sxx > U::zero() && sxy != U::zero() {
+            let b = sxy / sxx;
+            Some((mean_value - b * mean_raw, b))
+        } else {
+            None
+        }
+    }
+}
+
+fn interpolate<U: FloatCore>(p0: CalibrationPoint<U>, p1: CalibrationPoint<U>, raw: U) -> U {
+    p0.value + (p1.value - p0.value) * (raw - p0.raw) / (p1.raw - p0.raw)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    Full,
+    NotFinite,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(points: &[(f32, f32)]) -> CalibrationTable<f32> {
+        let mut table = CalibrationTable::default();
+        for &(raw, value) in points {
+            table.insert(CalibrationPoint { raw, value }).unwrap();
+        }
+        table
+    }
+
+    #[test]
+    fn points_are_sorted_and_replaced() {
+        let t = table(&[
+            (5_000.0, 500.0),
+            (0.0, 0.0),
+            (1_000.0, 100.0),
+            (1_010.0, 100.0),
+        ]);
+        assert!(t.is_valid());
+        assert_eq!(
+            t.points().iter().map(|p| p.raw).collect::<Vec<_>>(),
+            [0.0, 1_010.0, 5_000.0]
+        );
+    }
+
+    #[test]
+    fn insert_fails_when_full() {
+        let mut t = CalibrationTable::<f32>::default();
+        for i in 0..CALIBRATION_TABLE_CAPACITY {
+            let x = i as f32;
+            assert_eq!(t.insert(CalibrationPoint { raw: x, value: x }), Ok(()));
+        }
+        let p = CalibrationPoint {
+            raw: 100.0,
+            value: 100.0,
+        };
+        assert_eq!(t.insert(p), Err(Error::Full));
+    }
+
+    #[test]
+    fn single_point_is_not_enough() {
+        assert_eq!(table(&[]).map(1.0), None);
+        assert_eq!(table(&[(1_000.0, 100.0)]).map(1.0), None);
+    }
+
+    #[test]
+    fn piecewise_linear_mapping() {
+        // A slightly nonlinear cell: 10 raw units per gram at the low end,
+        // 9.5 at the high end.
+        let t = table(&[
+            (0.0, 0.0),
+            (1_000.0, 100.0),
+            (4_900.0, 500.0),
+            (9_650.0, 1_000.0),
+        ]);
+        assert_eq!(t.map(0.0), Some(0.0));
+        assert_eq!(t.map(500.0), Some(50.0));
+        assert_eq!(t.map(4_900.0), Some(500.0));
+        assert_eq!(t.map(7_275.0), Some(750.0));
+        // Extrapolation along the outermost segments
+        assert_eq!(t.map(-100.0), Some(-10.0));
+        assert_eq!(t.map(10_125.0), Some(1_050.0));
+    }
+
+    #[test]
+    fn least_squares_fallback() {
+        // Two references produced (almost) the same raw readout,
+        // so the table isn't monotonic.
+        let t = table(&[
+            (0.0, 0.0),
+            (1_000.0, 100.0),
+            (1_000.0, 101.0),
+            (2_000.0, 200.0),
+        ]);
+        let y = t.map(1_500.0).unwrap();
+        assert!((y - 150.25).abs() < 1e-3, "{y}");
+        assert!((t.unit().unwrap() - 10.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn invalid_table_is_detected() {
+        let mut t = table(&[(0.0, 0.0), (1_000.0, 100.0)]);
+        t.points.swap(0, 1);
+        assert!(!t.is_valid());
+        let mut t = table(&[]);
+        t.len = u32::MAX;
+        assert!(!t.is_valid());
+    }
+}
@@ -0,0 +1,180 @@
+use stuff::mq::{MessageProcessingStatus, MessageQueue};
+
+use crate::{
+    common::{AppMessage, Duration, Instant},
+    scale::Error,
+};
+
+/// How long a capture waits for the readout to settle before it's given up.
+pub const CAPTURE_TIMEOUT: Duration = Duration::secs(10);
+
+/// A reading that requires a stable readout.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Capture {
+    Tare,
+    /// The zero point for calibration.
+    Zero,
+    /// The unit, using a reference of the given mass in grams.
+    Unit(f32),
+    /// A multi-point calibration reference of the given mass, in grams.
+    Point(f32),
+    Dose,
+}
+
+impl Capture {
+    /// Whether a view waits for the outcome, i.e. whether it's answered with
+    /// [`crate::common::AppMessage::Captured`] or
+    /// [`crate::common::AppMessage::CaptureFailed`].
+    pub fn is_answered(&self) -> bool {
+        !matches!(self, Capture::Tare)
+    }
+}
+
+/// The capture waiting for the readout to settle.
+///
+/// There is at most one: a new request replaces the pending one, so e.g.
+/// tare presses while the pan wobbles result in a single tare. A capture
+/// that doesn't succeed within [`CAPTURE_TIMEOUT`], or fails for another
+/// reason than an unstable readout, is given up.
+#[derive(Default)]
+pub struct PendingCapture {
+    request: Option<(Capture, Instant)>,
+}
+
+impl PendingCapture {
+    pub fn request(&mut self, capture: Capture, now: Instant) {
+        self.request = Some((capture, now));
+    }
+
+    pub fn cancel(&mut self) {
+        self.request = None;
+    }
+
+    pub fn get(&self) -> Option<Capture> {
+        self.request.map(|(capture, _)| capture)
+    }
+
+    /// Attempt the pending capture. It's left pending while the readout is
+    /// unstable. Returns the capture if it's been given up, i.e. on another
+    /// error or once it times out.
+    pub fn poll<F>(&mut self, now: Instant, try_capture: F) -> Option<Capture>
+    where
+        F: FnOnce(Capture) -> Result<(), Error>,
+    {
+        let (capture, since) = self.request?;
+        match try_capture(capture) {
+            Ok(()) => {
+                self.request = None;
+                None
+            }
+            Err(Error::Unstable) if now - since < CAPTURE_TIMEOUT => None,
+            Err(_) => {
+                self.request = None;
+                Some(capture)
+            }
+        }
+    }
+}
+
+/// Drop the answers already queued when a capture is cancelled, otherwise
+/// the next view that captures would take them as its own.
+pub fn drop_answers<const N: usize>(mq: &mut MessageQueue<AppMessage, N>) {
+    mq.process(|m, _| match m {
+        AppMessage::Captured | AppMessage::CaptureFailed => MessageProcessingStatus::Processed,
+        _ => MessageProcessingStatus::Ignored,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: u64) -> Instant {
+        Instant::from_ticks(0) + Duration::secs(secs)
+    }
+
+    #[test]
+    fn capture_waits_for_a_stable_readout() {
+        let mut pending = PendingCapture::default();
+        assert_eq!(pending.poll(at(0), |_| panic!("nothing is pending")), None);
+
+        pending.request(Capture::Unit(100.0), at(0));
+        assert_eq!(pending.poll(at(1), |_| Err(Error::Unstable)), None);
+        assert_eq!(pending.get(), Some(Capture::Unit(100.0)));
+        assert_eq!(
+            pending.poll(at(2), |c| {
+                assert_eq!(c, Capture::Unit(100.0));
+                Ok(())
+            }),
+            None
+        );
+        assert_eq!(pending.get(), None);
+    }
+
+    #[test]
+    fn request_replaces_the_pending_one() {
+        let mut pending = PendingCapture::default();
+        pending.request(Capture::Tare, at(0));
+        pending.request(Capture::Tare, at(1));
+        pending.request(Capture::Dose, at(2));
+        let mut attempts = 0;
+        pending.poll(at(3), |c| {
+            assert_eq!(c, Capture::Dose);
+            attempts += 1;
+            Ok(())
+        });
+        assert_eq!(attempts, 1);
+        assert_eq!(pending.get(), None);
+    }
+
+    #[test]
+    fn capture_is_given_up_after_the_timeout() {
+        let mut pending = PendingCapture::default();
+        pending.request(Capture::Zero, at(5));
+        assert_eq!(pending.poll(at(14), |_| Err(Error::Unstable)), None);
+        assert_eq!(
+            pending.poll(at(15), |_| Err(Error::Unstable)),
+            Some(Capture::Zero)
+        );
+        assert_eq!(pending.get(), None);
+
+        pending.request(Capture::Tare, at(20));
+        pending.cancel();
+        assert_eq!(pending.poll(at(40), |_| panic!("nothing is pending")), None);
+    }
+
+    #[test]
+    fn capture_is_given_up_on_other_errors() {
+        let mut pending = PendingCapture::default();
+        pending.request(Capture::Unit(100.0), at(0));
+        assert_eq!(
+            pending.poll(at(1), |_| Err(Error::ZeroUnit)),
+            Some(Capture::Unit(100.0))
+        );
+        assert_eq!(pending.get(), None);
+    }
+
+    #[test]
+    fn answers_are_dropped() {
+        let mut mq = MessageQueue::<AppMessage, 4>::default();
+        mq.push(AppMessage::Captured);
+        mq.push(AppMessage::Tare);
+        mq.push(AppMessage::CaptureFailed);
+        drop_answers(&mut mq);
+        let mut left = Vec::new();
+        mq.process(|m, _| {
+            left.push(m.clone());
+            MessageProcessingStatus::Processed
+        });
+        assert_eq!(left, [AppMessage::Tare]);
+    }
+
+    #[test]
+    fn only_tares_are_unanswered() {
+        assert!(!Capture::Tare.is_answered());
+        assert!(Capture::Zero.is_answered());
+        assert!(Capture::Unit(1.0).is_answered());
+        assert!(Capture::Point(1.0).is_answered());
+        assert!(Capture::Dose.is_answered());
+    }
+}
@@ -0,0 +1,409 @@
+use alloc::boxed::Box;
+
+use crate::{
+    button::ButtonEvent,
+    common::{AppMessage, AppState, InputEvent, Instant},
+    dashboard::{handle_long_press, switch_mode},
+    entry::{Step, Tenths},
+    settings::Mode,
+    terminal::Terminal,
+    units::weight_row,
+    view_stack::{View, ViewContext},
+};
+use stuff::mq::MessageProcessingStatus;
+
+/// Below this weight the scale is empty, in grams, i.e. the portion has
+/// been taken away.
+const EMPTY_WEIGHT: f32 = 1.0;
+/// A settled weight below this fraction of the minimum isn't classified,
+/// e.g. a portion still being put together.
+const MIN_PORTION: f32 = 0.5;
+
+/// The largest nominal weight and tolerance, in tenths of a gram.
+const MAX_NOMINAL: u16 = 50_000;
+const MAX_TOLERANCE: u16 = 9_999;
+
+/// The accepted weights of a portion.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct CheckLimits {
+    /// In grams after the tare.
+    pub nominal: f32,
+    /// How much lighter than nominal a portion may be, in grams.
+    pub lower: f32,
+    /// How much heavier than nominal a portion may be, in grams.
+    pub upper: f32,
+}
+
+impl Default for CheckLimits {
+    fn default() -> Self {
+        Self {
+            nominal: 100.0,
+            lower: 2.0,
+            upper: 2.0,
+        }
+    }
+}
+
+impl CheckLimits {
+    pub fn min(&self) -> f32 {
+        self.nominal - self.lower
+    }
+
+    pub fn max(&self) -> f32 {
+        self.nominal + self.upper
+    }
+
+    pub fn classify(&self, weight: f32) -> CheckBand {
+        if weight < self.min() {
+            CheckBand::Under
+        } else if weight > self.max() {
+            CheckBand::Over
+        } else {
+            CheckBand::Ok
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CheckBand {
+    Under,
+    Ok,
+    Over,
+}
+
+impl CheckBand {
+    fn label(&self) -> &'static str {
+        match self {
+            CheckBand::Under => "UNDER",
+            CheckBand::Ok => "OK",
+            CheckBand::Over => "OVER",
+        }
+    }
+}
+
+/// Classifies the portions put on the scale one after another.
+///
+/// A portion is classified whenever its weight settles close enough to the
+/// limits, so it can still be corrected, and counted in its last band once
+/// it's taken away.
+pub struct CheckWeigher {
+    limits: CheckLimits,
+    result: Option<CheckBand>,
+}
+
+impl CheckWeigher {
+    pub fn new(limits: CheckLimits) -> Self {
+        Self {
+            limits,
+            result: None,
+        }
+    }
+
+    pub fn limits(&self) -> &CheckLimits {
+        &self.limits
+    }
+
+    /// The band of the portion on the scale, as of when it last settled.
+    pub fn result(&self) -> Option<CheckBand> {
+        self.result
+    }
+
+    /// Feed a readout. Returns the band of the portion to count once it's
+    /// taken away.
+    pub fn update(&mut self, weight: f32, is_stable: bool) -> Option<CheckBand> {
+        if weight < EMPTY_WEIGHT {
+            self.result.take()
+        } else {
+            if is_stable && weight >= self.limits.min() * MIN_PORTION {
+                self.result = Some(self.limits.classify(weight));
+            }
+            None
+        }
+    }
+}
+
+/// The main screen of [`Mode::Check`].
+///
+/// Button A tares, button B opens the [`CheckMenu`]. The counts are kept
+/// in [`AppState::check_counts`] until the limits change. The long presses
+/// are the same as on the dashboard.
+pub struct CheckView {
+    get_instant: fn() -> Instant,
+    weigher: CheckWeigher,
+}
+
+impl CheckView {
+    pub fn new(get_instant: fn() -> Instant) -> Self {
+        Self {
+            get_instant,
+            weigher: CheckWeigher::new(CheckLimits::default()),
+        }
+    }
+}
+
+impl View for CheckView {
+    fn handle_message(&mut self, m: &AppMessage, cx: &mut ViewContext) -> MessageProcessingStatus {
+        if let AppMessage::InputEvent(e) = m {
+            match e {
+                InputEvent::ButtonA(ButtonEvent::Press) => cx.send(AppMessage::Tare),
+                InputEvent::ButtonB(ButtonEvent::Press) => {
+                    cx.push_view(Box::new(CheckMenu::new(cx.state.check_limits)))
+                }
+                e => handle_long_press(e, cx),
+            }
+            MessageProcessingStatus::Processed
+        } else {
+            MessageProcessingStatus::Ignored
+        }
+    }
+
+    fn render(&mut self, terminal: &mut dyn Terminal, state: &AppState) -> core::fmt::Result {
+        let unit = state.settings.unit;
+        let division = unit.division(state.settings.division());
+        let limits = self.weigher.limits();
+        // The result is shown only while the weight is settled
+        let result = match self.weigher.result() {
+            Some(band) if state.is_stable => band.label(),
+            _ => "",
+        };
+        terminal.set_position(0, 0)?;
+        terminal.write_fmt(format_args!("CHECK{result:>11}\n\n"))?;
+        for (label, grams) in [
+            ("WEIGHT", state.weight),
+            ("MIN", limits.min()),
+            ("MAX", limits.max()),
+        ] {
+            terminal.write_fmt(format_args!(
+                "{}\n",
+                weight_row(label, grams, unit, division)
+            ))?;
+        }
+        terminal.write_fmt(format_args!(
+            "\nUNDER   OK  OVER\n{:>5}{:>5}{:>6}",
+            state.check_counts[CheckBand::Under as usize],
+            state.check_counts[CheckBand::Ok as usize],
+            state.check_counts[CheckBand::Over as usize],
+        ))
+    }
+
+    fn update(&mut self, cx: &mut ViewContext) {
+        if switch_mode(Mode::Check, self.get_instant, cx) {
+            return;
+        }
+        if *self.weigher.limits() != cx.state.check_limits {
+            self.weigher = CheckWeigher::new(cx.state.check_limits);
+        }
+        if let Some(band) = self.weigher.update(cx.state.weight, cx.state.is_stable) {
+            cx.send(AppMessage::CountPortion(band));
+        }
+    }
+}
+
+/// Check limits menu.
+///
+/// A long press on button A selects the next field, buttons A and B change
+/// it. A long press on button B sets the limits and closes the menu.
+pub struct CheckMenu {
+    initial: CheckLimits,
+    /// The nominal weight and the lower and upper tolerances.
+    values: [Tenths; 3],
+    step: Step,
+    /// The index of a value, or of the step after them.
+    field: usize,
+}
+
+impl CheckMenu {
+    const LABELS: [&'static str; 4] = ["NOMINAL", "LOWER", "UPPER", "STEP"];
+
+    pub fn new(limits: CheckLimits) -> Self {
+        Self {
+            initial: limits,
+            values: [
+                Tenths::from_grams(limits.nominal, 1, MAX_NOMINAL),
+                Tenths::from_grams(limits.lower, 0, MAX_TOLERANCE),
+                Tenths::from_grams(limits.upper, 0, MAX_TOLERANCE),
+            ],
+            step: Step::default(),
+            field: 0,
+        }
+    }
+
+    fn limits(&self) -> CheckLimits {
+        let [nominal, lower, upper] = self.values.map(|v| v.grams());
+        CheckLimits {
+            nominal,
+            lower,
+            upper,
+        }
+    }
+
+    fn change(&mut self, forward: bool) {
+        match self.values.get_mut(self.field) {
+            Some(value) => value.change(self.step, forward),
+            None => self.step.change(forward),
+        }
+    }
+
+    fn handle_input(&mut self, e: &InputEvent, cx: &mut ViewContext) {
+        match e {
+            InputEvent::ButtonA(ButtonEvent::Press) => self.change(true),
+            InputEvent::ButtonB(ButtonEvent::Press) => self.change(false),
+            InputEvent::ButtonA(ButtonEvent::LongPress) => {
+                self.field = (self.field + 1) % Self::LABELS.len()
+            }
+            InputEvent::ButtonB(ButtonEvent::LongPress) => {
+                let limits = self.limits();
+                if limits != self.initial {
+                    cx.send(AppMessage::SetCheckLimits(limits));
+                }
+                cx.pop_view();
+            }
+        }
+    }
+}
+
+impl View for CheckMenu {
+    fn handle_message(&mut self, m: &AppMessage, cx: &mut ViewContext) -> MessageProcessingStatus {
+        if let AppMessage::InputEvent(e) = m {
+            self.handle_input(e, cx);
+            MessageProcessingStatus::Processed
+        } else {
+            MessageProcessingStatus::Ignored
+        }
+    }
+
+    fn render(&mut self, terminal: &mut dyn Terminal, _state: &AppState) -> core::fmt::Result {
+        terminal.set_position(0, 0)?;
+        terminal.write_str("CHECK\n")?;
+        for (i, label) in Self::LABELS.iter().enumerate() {
+            let marker = if i == self.field { '>' } else { ' ' };
+            let grams = self.values.get(i).map_or(self.step.grams(), |v| v.grams());
+            terminal.write_fmt(format_args!("{marker}{label:<7}{grams:>6.1} g\n"))?;
+        }
+        terminal.write_str("\nA/B: +/-\nHOLD A: NEXT\nHOLD B: DONE")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::String;
+
+    use super::*;
+    use crate::{
+        common::AppContext,
+        testing::{input, show, take_messages},
+    };
+    use stuff::run_loop::{Task, TaskStatus};
+
+    #[test]
+    fn weight_is_classified() {
+        let limits = CheckLimits {
+            nominal: 100.0,
+            lower: 1.0,
+            upper: 3.0,
+        };
+        assert_eq!(limits.classify(98.9), CheckBand::Under);
+        assert_eq!(limits.classify(99.0), CheckBand::Ok);
+        assert_eq!(limits.classify(103.0), CheckBand::Ok);
+        assert_eq!(limits.classify(103.1), CheckBand::Over);
+    }
+
+    #[test]
+    fn portions_are_counted_once_taken_away() {
+        let mut w = CheckWeigher::new(CheckLimits::default());
+        assert_eq!(w.update(0.0, true), None);
+        assert_eq!(w.update(50.0, false), None);
+        assert_eq!(w.update(96.0, true), None);
+        assert_eq!(w.result(), Some(CheckBand::Under));
+        // Corrected
+        assert_eq!(w.update(99.0, false), None);
+        assert_eq!(w.update(100.5, true), None);
+        assert_eq!(w.result(), Some(CheckBand::Ok));
+        assert_eq!(w.update(0.2, false), Some(CheckBand::Ok));
+        assert_eq!(w.result(), None);
+
+        w.update(110.0, true);
+        assert_eq!(w.update(0.0, true), Some(CheckBand::Over));
+        assert_eq!(w.update(0.0, true), None);
+    }
+
+    #[test]
+    fn only_weights_close_to_the_limits_are_classified() {
+        let mut w = CheckWeigher::new(CheckLimits::default());
+        // Half of the minimum of 98 g
+        w.update(48.9, true);
+        assert_eq!(w.result(), None);
+        assert_eq!(w.update(0.0, true), None);
+        w.update(49.0, true);
+        assert_eq!(w.result(), Some(CheckBand::Under));
+    }
+
+    #[test]
+    fn result_is_shown_once_settled() {
+        let mut cx = AppContext::default();
+        cx.state.settings.mode = Mode::Check;
+        let (mut stack, terminal) = show(Box::new(CheckView::new(|| Instant::from_ticks(0))));
+
+        cx.state.weight = 101.0;
+        stack.run(&mut cx);
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(0), "CHECK");
+
+        cx.state.is_stable = true;
+        stack.run(&mut cx);
+        stack.run(&mut cx);
+        cx.state.weight = 0.0;
+        stack.run(&mut cx);
+        stack.run(&mut cx);
+        assert_eq!(
+            take_messages(&mut cx),
+            [AppMessage::CountPortion(CheckBand::Ok)]
+        );
+        // What the main loop does
+        cx.state.check_counts[CheckBand::Ok as usize] += 1;
+        cx.state.weight = 97.5;
+        stack.run(&mut cx);
+        stack.run(&mut cx);
+        let lines: [String; 8] = core::array::from_fn(|i| terminal.borrow().line(i));
+        assert_eq!(
+            lines,
+            [
+                "CHECK      UNDER",
+                "",
+                "WEIGHT    97.5 g",
+                "MIN       98.0 g",
+                "MAX      102.0 g",
+                "",
+                "UNDER   OK  OVER",
+                "    0    1     0",
+            ]
+        );
+    }
+
+    #[test]
+    fn limits_are_entered() {
+        let mut cx = AppContext::default();
+        let (mut stack, terminal) = show(Box::new(CheckMenu::new(CheckLimits::default())));
+
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::Press));
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::LongPress));
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::Press));
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::Press));
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::Press));
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(1), " NOMINAL  99.0 g");
+        assert_eq!(terminal.borrow().line(2), ">LOWER     0.0 g");
+        assert_eq!(terminal.borrow().line(4), " STEP      1.0 g");
+
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::LongPress));
+        assert!(matches!(stack.run(&mut cx), TaskStatus::Done));
+        assert_eq!(
+            take_messages(&mut cx),
+            [AppMessage::SetCheckLimits(CheckLimits {
+                nominal: 99.0,
+                lower: 0.0,
+                upper: 2.0,
+            })]
+        );
+    }
+}
@@ -3,7 +3,18 @@ use stuff::{
     run_loop::{FnTask, Task},
 };
 
-use crate::{button::ButtonEvent, dashboard::Dashboard, input_scanner::InputScanner};
+use crate::{
+    auto_off::AutoOff,
+    button::ButtonEvent,
+    check::{CheckBand, CheckLimits},
+    input_scanner::InputScanner,
+    name::Name,
+    profile::PROFILE_COUNT,
+    recipe::{Recipe, RECIPE_COUNT},
+    settings::Settings,
+    target::Target,
+    view_stack::ViewStack,
+};
 
 pub type Instant = fugit::Instant<u64, 1, 1_000_000>;
 pub type Duration = fugit::Duration<u64, 1, 1_000_000>;
@@ -14,12 +25,57 @@ pub struct AppContext {
     pub state: AppState,
 }
 
+#[derive(Clone, PartialEq, Debug)]
 pub enum AppMessage {
     InputEvent(InputEvent),
+    /// Tare once the readout is stable.
     Tare,
-    Calibrate,
+    /// Capture the zero point for calibration. Answered with `Captured`.
+    CaptureZero,
+    /// Calibrate using a reference of the given mass, in grams.
+    /// Answered with `Captured`.
+    Calibrate(f32),
+    /// Add a reference of the given mass, in grams, to the calibration
+    /// started with `Calibrate`. Answered with `Captured`.
+    AddCalibrationPoint(f32),
+    /// Indicates that `CaptureZero`, `Calibrate`, `AddCalibrationPoint` or
+    /// `CaptureDose` has been completed.
+    Captured,
+    /// Indicates that `CaptureZero`, `Calibrate`, `AddCalibrationPoint` or
+    /// `CaptureDose` has been given up because the readout didn't settle, cf.
+    /// [`crate::capture::CAPTURE_TIMEOUT`].
+    CaptureFailed,
+    /// Withdraw the pending `CaptureZero`, `Calibrate`, `AddCalibrationPoint`
+    /// or `CaptureDose`, dropping its answer if it's already queued.
+    CancelCapture,
+    /// Persist the current calibration.
+    SaveCalibration,
+    /// Restore the persisted calibration.
+    RevertCalibration,
+    /// Apply the settings (without saving).
+    ChangeSettings(Settings),
+    /// Persist the current settings.
+    SaveSettings,
+    /// Activate and persist the calibration profile with the given index.
+    SelectProfile(usize),
+    /// Rename and persist the calibration profile with the given index.
+    RenameProfile(usize, Name),
+    /// Capture and persist the current weight as the dose.
+    /// Answered with `Captured`.
+    CaptureDose,
+    /// Set (or clear) and persist the dose, in grams.
+    SetDose(Option<f32>),
+    /// Set and persist the target of [`crate::settings::Mode::Target`].
+    SetTarget(Target),
+    /// Set and persist the limits of [`crate::settings::Mode::Check`],
+    /// resetting the counts.
+    SetCheckLimits(CheckLimits),
+    /// Count a portion of [`crate::settings::Mode::Check`] taken away in its
+    /// band.
+    CountPortion(CheckBand),
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum InputEvent {
     ButtonA(ButtonEvent),
     ButtonB(ButtonEvent),
@@ -28,11 +84,29 @@ pub enum InputEvent {
 #[derive(Default)]
 pub struct AppState {
     pub weight: f32,
+    pub is_stable: bool,
+    /// The flow rate, in g/s.
+    pub flow_rate: f32,
+    /// The finest step of the readout, in grams.
+    pub resolution: f32,
+    /// The weight the brew ratio is relative to, in grams.
+    pub dose: Option<f32>,
+    /// The reference mass used for the last calibration, in grams.
+    pub calibration_mass: f32,
+    pub settings: Settings,
+    pub active_profile: usize,
+    pub profile_names: [Name; PROFILE_COUNT],
+    pub recipes: [Recipe; RECIPE_COUNT],
+    pub target: Target,
+    pub check_limits: CheckLimits,
+    /// The number of portions in each band, indexed by [`CheckBand`].
+    pub check_counts: [u32; 3],
 }
 
 pub enum AppTask<'a> {
     InputScanner(InputScanner<'a>),
-    Dashboard(Dashboard),
+    AutoOff(AutoOff<'a>),
+    ViewStack(ViewStack),
     Fn(FnTask<'a, AppContext>),
 }
 
@@ -40,7 +114,8 @@ impl<'a> AsMut<dyn Task<AppContext> + 'a> for AppTask<'a> {
     fn as_mut(&mut self) -> &mut (dyn Task<AppContext> + 'a) {
         match self {
             AppTask::InputScanner(task) => task,
-            AppTask::Dashboard(task) => task,
+            AppTask::AutoOff(task) => task,
+            AppTask::ViewStack(task) => task,
             AppTask::Fn(task) => task,
         }
     }
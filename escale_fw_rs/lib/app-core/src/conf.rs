@@ -0,0 +1,360 @@
+//! The persistent configuration.
+//!
+//! The configuration is stored in the [`persist::kv`] store under
+//! [`CONF_KEY`] as the payload version followed by an explicitly encoded
+//! payload. Later versions only ever append fields, so a field missing in
+//! an older version takes the default value. Each field is validated on its
+//! own, so a bad one doesn't invalidate the rest.
+//!
+//! The first firmware stored just the scale unit in the last flash sector
+//! (see [`Conf::decode_baseline`]), it's migrated until the configuration
+//! is saved.
+
+use persist::{
+    codec::{self, Reader, Writer},
+    kv::{self, Key, Store},
+    storage::{Storage, ERASED},
+};
+
+use crate::{
+    calibration_table::{CalibrationPoint, CalibrationTable},
+    check::CheckLimits,
+    name::Name,
+    profile::{numbered_name, Profile, PROFILE_COUNT},
+    settings::{Settings, SETTINGS_SIZE},
+    target::Target,
+};
+
+pub const CONF_KEY: Key = 1;
+pub const CONF_VERSION: u16 = 1;
+/// The size of the stored configuration.
+pub const CONF_SIZE: usize = 512;
+
+/// The size of the configuration of the first firmware.
+const BASELINE_SIZE: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Conf {
+    pub profiles: [Profile; PROFILE_COUNT],
+    pub active_profile: usize,
+    pub settings: Settings,
+    /// See [`crate::common::AppState::dose`].
+    pub dose: Option<f32>,
+    pub target: Target,
+    pub check_limits: CheckLimits,
+}
+
+impl Default for Conf {
+    fn default() -> Self {
+        Self {
+            profiles: core::array::from_fn(|i| Profile::new(numbered_name(i))),
+            active_profile: 0,
+            settings: Default::default(),
+            dose: None,
+            target: Default::default(),
+            check_limits: Default::default(),
+        }
+    }
+}
+
+impl Conf {
+    /// Load the configuration from the store, or from the sector the first
+    /// firmware stored it in (the first sector of `baseline`) if it's not
+    /// there yet.
+    pub fn load<S: Storage, L: Storage>(store: &Store<S>, baseline: &L) -> Self {
+        let mut value = [0; CONF_SIZE];
+        let conf = match store.get(CONF_KEY, &mut value) {
+            Ok(Some(len)) => Self::decode(&value[..len]),
+            _ => None,
+        };
+        conf.or_else(|| {
+            let mut bytes = [ERASED; BASELINE_SIZE];
+            baseline.read(0, &mut bytes).ok()?;
+            Self::decode_baseline(&bytes)
+        })
+        .unwrap_or_default()
+    }
+
+    pub fn save<S: Storage>(&self, store: &mut Store<S>) -> Result<(), kv::Error<S::Error>> {
+        let (value, len) = self.encode();
+        store.set(CONF_KEY, &value[..len])
+    }
+
+    /// Encode the configuration in the current version.
+    ///
+    /// Returns the buffer and the length of the encoded value.
+    pub fn encode(&self) -> ([u8; CONF_SIZE], usize) {
+        const E: &str = "The configuration must fit the buffer";
+        let mut bytes = [0; CONF_SIZE];
+        let mut w = Writer::new(&mut bytes);
+        w.u16(CONF_VERSION).expect(E);
+        w.u8(self.profiles.len() as u8).expect(E);
+        for profile in &self.profiles {
+            write_name_and_zero(&mut w, profile).expect(E);
+            write_calibration(&mut w, profile).expect(E);
+        }
+        w.u8(self.active_profile as u8).expect(E);
+        w.bytes(&self.settings.to_bytes()).expect(E);
+        // Zero for no dose
+        w.f32(self.dose.unwrap_or(0.0)).expect(E);
+        w.f32(self.target.weight).expect(E);
+        w.f32(self.target.tolerance).expect(E);
+        w.f32(self.check_limits.nominal).expect(E);
+        w.f32(self.check_limits.lower).expect(E);
+        w.f32(self.check_limits.upper).expect(E);
+        let len = w.len();
+        (bytes, len)
+    }
+
+    /// Decode a value of the current or a later version.
+    pub fn decode(value: &[u8]) -> Option<Self> {
+        let mut r = Reader::new(value);
+        if r.u16()? < 1 {
+            return None;
+        }
+        let mut conf = Self::default();
+        let count = r.u8().unwrap_or(0);
+        for profile in conf.profiles.iter_mut().take(count as usize) {
+            read_name_and_zero(&mut r, profile);
+            read_calibration(&mut r, profile);
+        }
+        if let Some(i) = r.u8().map(usize::from).filter(|&i| i < PROFILE_COUNT) {
+            conf.active_profile = i;
+        }
+        if let Some(settings) = r.bytes::<SETTINGS_SIZE>() {
+            conf.settings = Settings::from_bytes(settings);
+        }
+        conf.dose = r.f32().filter(|&d| is_valid_mass(d));
+        if let Some(weight) = r.f32().filter(|&w| is_valid_mass(w)) {
+            conf.target.weight = weight;
+        }
+        if let Some(tolerance) = r.f32().filter(|&t| is_valid_tolerance(t)) {
+            conf.target.tolerance = tolerance;
+        }
+        if let Some(nominal) = r.f32().filter(|&n| is_valid_mass(n)) {
+            conf.check_limits.nominal = nominal;
+        }
+        if let Some(lower) = r.f32().filter(|&t| is_valid_tolerance(t)) {
+            conf.check_limits.lower = lower;
+        }
+        if let Some(upper) = r.f32().filter(|&t| is_valid_tolerance(t)) {
+            conf.check_limits.upper = upper;
+        }
+        Some(conf)
+    }
+
+    /// Decode the `repr(C)` struct stored by the first firmware:
+    ///
+    /// ```text
+    /// format: u16,        // 1
+    /// scale_unit: f32,
+    /// ```
+    fn decode_baseline(bytes: &[u8]) -> Option<Self> {
+        let mut r = Reader::new(bytes);
+        if r.u16()? != 1 {
+            return None;
+        }
+        let mut conf = Self::default();
+        let mut r = Reader::new(bytes.get(4..).unwrap_or_default());
+        if let Some(unit) = r.f32().filter(|&u| is_valid_unit(u)) {
+            conf.profiles[0].unit = unit;
+        }
+        Some(conf)
+    }
+}
+
+fn write_calibration(w: &mut Writer, profile: &Profile) -> Result<(), codec::Error> {
+    w.f32(profile.unit)?;
+    let points = profile.calibration.points();
+    w.u8(points.len() as u8)?;
+    for p in points {
+        w.f32(p.raw)?;
+        w.f32(p.value)?;
+    }
+    w.f32(profile.calibration_mass)
+}
+
+/// A missing zero is stored as NaN.
+fn write_name_and_zero(w: &mut Writer, profile: &Profile) -> Result<(), codec::Error> {
+    w.bytes(&profile.name.to_bytes())?;
+    w.f32(profile.zero.unwrap_or(f32::NAN))
+}
+
+/// Read the fields present and valid into the profile.
+fn read_calibration(r: &mut Reader, profile: &mut Profile) {
+    if let Some(unit) = r.f32().filter(|&u| is_valid_unit(u)) {
+        profile.unit = unit;
+    }
+    if let Some(len) = r.u8() {
+        let mut table = CalibrationTable::default();
+        for _ in 0..len {
+            if let (Some(raw), Some(value)) = (r.f32(), r.f32()) {
+                _ = table.insert(CalibrationPoint { raw, value });
+            }
+        }
+        profile.calibration = table;
+    }
+    if let Some(mass) = r.f32().filter(|&m| is_valid_mass(m)) {
+        profile.calibration_mass = mass;
+    }
+}
+
+/// Read the fields present and valid into the profile.
+fn read_name_and_zero(r: &mut Reader, profile: &mut Profile) {
+    if let Some(name) = r.bytes().and_then(Name::from_bytes) {
+        profile.name = name;
+    }
+    profile.zero = r.f32().filter(|z| z.is_finite());
+}
+
+fn is_valid_unit(unit: f32) -> bool {
+    unit.is_finite() && unit != 0.0
+}
+
+fn is_valid_mass(mass: f32) -> bool {
+    mass.is_finite() && mass > 0.0
+}
+
+fn is_valid_tolerance(tolerance: f32) -> bool {
+    tolerance == 0.0 || is_valid_mass(tolerance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        settings::{FilterStrength, Mode},
+        units::WeightUnit,
+    };
+    use persist::storage::RamStorage;
+
+    fn calibration(points: &[(f32, f32)]) -> CalibrationTable<f32> {
+        let mut table = CalibrationTable::default();
+        for &(raw, value) in points {
+            table.insert(CalibrationPoint { raw, value }).unwrap();
+        }
+        table
+    }
+
+    fn conf() -> Conf {
+        let mut conf = Conf {
+            settings: Settings {
+                unit: WeightUnit::Ounces,
+                brightness: 1,
+                filter_strength: FilterStrength::High,
+                auto_off_minutes: 0,
+                auto_tare: false,
+                pour_settle_seconds: 10,
+                mode: Mode::Espresso,
+                ..Default::default()
+            },
+            active_profile: 1,
+            dose: Some(18.5),
+            target: Target {
+                weight: 250.0,
+                tolerance: 1.5,
+            },
+            check_limits: CheckLimits {
+                nominal: 125.0,
+                lower: 0.0,
+                upper: 5.0,
+            },
+            ..Default::default()
+        };
+        conf.profiles[0] = Profile {
+            name: Name::from_bytes(*b"ESPRESSO").unwrap(),
+            zero: Some(-1_234.5),
+            unit: 9.9,
+            calibration: calibration(&[(0.0, 0.0), (1_000.0, 100.0), (4_900.0, 500.0)]),
+            calibration_mass: 500.0,
+        };
+        conf.profiles[1] = Profile {
+            name: Name::from_bytes(*b"KITCHEN ").unwrap(),
+            zero: Some(56_789.0),
+            unit: 1.5,
+            calibration: calibration(&[(0.0, 0.0), (3_000.0, 2_000.0)]),
+            calibration_mass: 2_000.0,
+        };
+        conf
+    }
+
+    /// The `repr(C)` layout of the first firmware on a little-endian target.
+    fn baseline(format: u16, unit: f32) -> RamStorage<4096, 1> {
+        let mut bytes = [ERASED; BASELINE_SIZE];
+        bytes[0..2].copy_from_slice(&format.to_le_bytes());
+        bytes[4..8].copy_from_slice(&unit.to_le_bytes());
+        let mut storage = RamStorage::default();
+        storage.program(0, &bytes).unwrap();
+        storage
+    }
+
+    #[test]
+    fn conf_is_saved_and_loaded() {
+        let erased = RamStorage::<4096, 1>::default();
+        let mut store = Store::mount(RamStorage::<1024, 4>::default()).unwrap();
+        assert_eq!(Conf::load(&store, &erased), Conf::default());
+        conf().save(&mut store).unwrap();
+        let store = Store::mount(store.into_storage()).unwrap();
+        assert_eq!(Conf::load(&store, &erased), conf());
+    }
+
+    #[test]
+    fn baseline_conf_is_migrated_until_saved() {
+        let baseline = baseline(1, 9.9);
+        let mut store = Store::mount(RamStorage::<1024, 4>::default()).unwrap();
+        let mut expected = Conf::default();
+        expected.profiles[0].unit = 9.9;
+        assert_eq!(Conf::load(&store, &baseline), expected);
+        Conf::default().save(&mut store).unwrap();
+        assert_eq!(Conf::load(&store, &baseline), Conf::default());
+    }
+
+    #[test]
+    fn invalid_baseline_conf_is_ignored() {
+        let store = Store::mount(RamStorage::<1024, 4>::default()).unwrap();
+        for baseline in [baseline(0, 9.9), baseline(2, 9.9), baseline(1, f32::NAN)] {
+            assert_eq!(Conf::load(&store, &baseline), Conf::default());
+        }
+    }
+
+    #[test]
+    fn conf_round_trip() {
+        let conf = conf();
+        let (value, len) = conf.encode();
+        assert_eq!(Conf::decode(&value[..len]), Some(conf));
+        let (value, len) = Conf::default().encode();
+        assert_eq!(Conf::decode(&value[..len]), Some(Conf::default()));
+    }
+
+    #[test]
+    fn invalid_fields_are_replaced_individually() {
+        let mut conf = conf();
+        conf.profiles[0].unit = f32::NAN;
+        conf.profiles[1].calibration_mass = -1.0;
+        conf.profiles[1].zero = Some(f32::INFINITY);
+        conf.active_profile = PROFILE_COUNT;
+        let (value, len) = conf.encode();
+        let decoded = Conf::decode(&value[..len]).unwrap();
+        let default = Conf::default();
+        assert_eq!(decoded.profiles[0].unit, default.profiles[0].unit);
+        assert_eq!(
+            decoded.profiles[0].calibration,
+            conf.profiles[0].calibration
+        );
+        assert_eq!(
+            decoded.profiles[1].calibration_mass,
+            default.profiles[1].calibration_mass
+        );
+        assert_eq!(decoded.profiles[1].zero, default.profiles[1].zero);
+        assert_eq!(decoded.profiles[1].name, conf.profiles[1].name);
+        assert_eq!(decoded.active_profile, 0);
+        assert_eq!(decoded.settings, conf.settings);
+    }
+
+    #[test]
+    fn damaged_or_erased_conf_is_rejected() {
+        assert_eq!(Conf::decode(&[]), None);
+        assert_eq!(Conf::decode(&[0, 0]), None);
+        assert_eq!(Conf::decode_baseline(&[ERASED; BASELINE_SIZE]), None);
+    }
+}
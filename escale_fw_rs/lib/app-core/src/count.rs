@@ -0,0 +1,215 @@
+use crate::{
+    button::ButtonEvent,
+    common::{AppMessage, AppState, InputEvent, Instant},
+    dashboard::{handle_long_press, switch_mode},
+    settings::Mode,
+    terminal::Terminal,
+    units::weight_row,
+    view_stack::{View, ViewContext},
+};
+use stuff::mq::MessageProcessingStatus;
+
+/// A piece lighter than this many resolution steps of the scale may be
+/// miscounted, as the noise of the readout is a few steps.
+const MIN_PIECE_STEPS: f32 = 10.0;
+
+/// Counts pieces by their average weight.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PieceCounter {
+    piece_weight: f32,
+}
+
+impl PieceCounter {
+    /// Derive the average piece weight from the weight of the given number of
+    /// reference pieces. Fails unless the weight is positive.
+    pub fn from_sample(weight: f32, pieces: u8) -> Option<Self> {
+        (weight > 0.0 && pieces > 0).then(|| Self {
+            piece_weight: weight / pieces as f32,
+        })
+    }
+
+    /// The average piece weight, in grams.
+    pub fn piece_weight(&self) -> f32 {
+        self.piece_weight
+    }
+
+    /// The number of pieces of the given weight, negative when they've been
+    /// taken away after the tare.
+    pub fn count(&self, weight: f32) -> i32 {
+        libm::roundf(weight / self.piece_weight) as i32
+    }
+
+    /// Whether a piece is too light to be told apart from the noise of a
+    /// scale of the given resolution, in grams.
+    pub fn is_too_light(&self, resolution: f32) -> bool {
+        self.piece_weight < resolution * MIN_PIECE_STEPS
+    }
+}
+
+/// The main screen of [`Mode::Count`].
+///
+/// Button A tares. Button B takes the weight of the reference pieces, as
+/// many as set in the settings, once it's stable and positive, and
+/// discards it when counting. The long presses are the same as on the dashboard.
+pub struct CountView {
+    get_instant: fn() -> Instant,
+    counter: Option<PieceCounter>,
+    is_sampling: bool,
+    /// Whether the rows that change with the state are rendered.
+    is_state_rendered: bool,
+}
+
+impl CountView {
+    pub fn new(get_instant: fn() -> Instant) -> Self {
+        Self {
+            get_instant,
+            counter: None,
+            is_sampling: false,
+            is_state_rendered: false,
+        }
+    }
+}
+
+impl View for CountView {
+    fn handle_message(&mut self, m: &AppMessage, cx: &mut ViewContext) -> MessageProcessingStatus {
+        if let AppMessage::InputEvent(e) = m {
+            match e {
+                InputEvent::ButtonA(ButtonEvent::Press) => cx.send(AppMessage::Tare),
+                InputEvent::ButtonB(ButtonEvent::Press) => {
+                    if self.counter.take().is_none() {
+                        self.is_sampling = !self.is_sampling;
+                    }
+                    self.is_state_rendered = false;
+                }
+                e => handle_long_press(e, cx),
+            }
+            MessageProcessingStatus::Processed
+        } else {
+            MessageProcessingStatus::Ignored
+        }
+    }
+
+    fn render(&mut self, terminal: &mut dyn Terminal, state: &AppState) -> core::fmt::Result {
+        if !self.is_state_rendered {
+            terminal.clear()?;
+            self.is_state_rendered = true;
+        }
+        let unit = state.settings.unit;
+        let division = unit.division(state.settings.division());
+        let weight = weight_row("WEIGHT", state.weight, unit, division);
+        terminal.set_position(0, 0)?;
+        let Some(counter) = &self.counter else {
+            terminal.write_fmt(format_args!(
+                "COUNT\n\nPLACE {} PCS\n\n{weight}\n\n\n",
+                state.settings.sample_size,
+            ))?;
+            let hint = if !self.is_sampling {
+                "B: SAMPLE"
+            } else if state.is_stable && state.weight <= 0.0 {
+                "ADD PIECES"
+            } else {
+                "HOLD STILL..."
+            };
+            return terminal.write_fmt(format_args!("{hint:16}"));
+        };
+        terminal.write_fmt(format_args!(
+            "COUNT\n\n{:>12} PCS\n\n{weight}\n{}\n\n{}",
+            counter.count(state.weight),
+            // Finer than the readout, a piece may weigh less than a division
+            weight_row("PIECE", counter.piece_weight(), unit, division / 100.0),
+            if counter.is_too_light(state.resolution) {
+                "SAMPLE TOO LIGHT"
+            } else {
+                ""
+            },
+        ))
+    }
+
+    fn update(&mut self, cx: &mut ViewContext) {
+        if switch_mode(Mode::Count, self.get_instant, cx) {
+            return;
+        }
+        // Sampling goes on until there's something on the scale
+        if self.is_sampling
+            && cx.state.is_stable
+            && let Some(counter) =
+                PieceCounter::from_sample(cx.state.weight, cx.state.settings.sample_size)
+        {
+            self.counter = Some(counter);
+            self.is_sampling = false;
+            self.is_state_rendered = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::String;
+
+    use super::*;
+    use crate::{
+        common::AppContext,
+        testing::{input, show},
+    };
+    use stuff::run_loop::Task;
+
+    #[test]
+    fn pieces_are_counted() {
+        let counter = PieceCounter::from_sample(12.5, 10).unwrap();
+        assert_eq!(counter.piece_weight(), 1.25);
+        assert_eq!(counter.count(0.4), 0);
+        assert_eq!(counter.count(61.9), 50);
+        assert_eq!(counter.count(-2.6), -2);
+        assert!(!counter.is_too_light(0.1));
+        assert!(counter.is_too_light(0.2));
+        assert_eq!(PieceCounter::from_sample(0.0, 10), None);
+    }
+
+    #[test]
+    fn sample_is_taken_once_stable() {
+        let mut cx = AppContext::default();
+        cx.state.settings.mode = Mode::Count;
+        cx.state.settings.sample_size = 20;
+        let (mut stack, terminal) = show(Box::new(CountView::new(|| Instant::from_ticks(0))));
+
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(2), "PLACE 20 PCS");
+        assert_eq!(terminal.borrow().line(7), "B: SAMPLE");
+
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::Press));
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(7), "HOLD STILL...");
+
+        cx.state.is_stable = true;
+        stack.run(&mut cx);
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(2), "PLACE 20 PCS");
+        assert_eq!(terminal.borrow().line(7), "ADD PIECES");
+
+        cx.state.weight = 5.0;
+        stack.run(&mut cx);
+        stack.run(&mut cx);
+        cx.state.weight = 13.2;
+        cx.state.resolution = 0.05;
+        stack.run(&mut cx);
+        let lines: [String; 8] = core::array::from_fn(|i| terminal.borrow().line(i));
+        assert_eq!(
+            lines,
+            [
+                "COUNT",
+                "",
+                "          53 PCS",
+                "",
+                "WEIGHT    13.2 g",
+                "PIECE    0.250 g",
+                "",
+                "SAMPLE TOO LIGHT",
+            ]
+        );
+
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::Press));
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(2), "PLACE 20 PCS");
+        assert_eq!(terminal.borrow().line(7), "B: SAMPLE");
+    }
+}
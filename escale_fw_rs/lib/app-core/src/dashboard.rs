@@ -1,89 +1,140 @@
-use alloc::{format, rc::Rc, string::String};
-use core::cell::RefCell;
-use libm::fabsf;
+use alloc::{boxed::Box, format, string::String};
 
 use crate::{
     button::ButtonEvent,
-    common::{AppContext, AppMessage, Duration, InputEvent, Instant},
+    calibration::CalibrationWizard,
+    check::CheckView,
+    common::{AppMessage, AppState, Duration, InputEvent, Instant},
+    count::CountView,
+    dose::format_ratio,
+    espresso::EspressoView,
+    percent::PercentView,
+    pour::{PourDetection, PourDetector, PourEvent},
+    quantizer::Quantizer,
+    recipe::RecipeView,
+    settings::{Mode, Settings, SettingsMenu},
+    target::TargetView,
     terminal::Terminal,
+    units::{clamp_inf, WeightUnit},
+    view_stack::{View, ViewContext},
 };
-use stuff::{
-    mq::MessageProcessingStatus,
-    run_loop::{Task, TaskStatus},
-};
+use stuff::mq::MessageProcessingStatus;
+
+/// The main screen of the mode.
+pub fn home_view(mode: Mode, get_instant: fn() -> Instant) -> Box<dyn View> {
+    match mode {
+        Mode::Normal => Box::new(Dashboard::new(get_instant)),
+        Mode::Espresso => Box::new(EspressoView::new(get_instant)),
+        Mode::Recipe => Box::new(RecipeView::new(get_instant)),
+        Mode::Target => Box::new(TargetView::new(get_instant)),
+        Mode::Check => Box::new(CheckView::new(get_instant)),
+        Mode::Count => Box::new(CountView::new(get_instant)),
+        Mode::Percent => Box::new(PercentView::new(get_instant)),
+    }
+}
+
+/// The long presses shared by the main screens: button A opens the
+/// [`CalibrationWizard`], button B the [`SettingsMenu`]. The short presses
+/// are up to each screen.
+pub(crate) fn handle_long_press(e: &InputEvent, cx: &mut ViewContext) {
+    match e {
+        InputEvent::ButtonA(ButtonEvent::LongPress) => {
+            cx.push_view(Box::new(CalibrationWizard::new(cx.state.calibration_mass)))
+        }
+        InputEvent::ButtonB(ButtonEvent::LongPress) => {
+            cx.push_view(Box::new(SettingsMenu::new(cx.state.settings)))
+        }
+        _ => {}
+    }
+}
+
+/// Replace the main screen of `mode` with the one of the selected mode if
+/// the settings changed it. Returns whether it was replaced.
+pub(crate) fn switch_mode(mode: Mode, get_instant: fn() -> Instant, cx: &mut ViewContext) -> bool {
+    let selected = cx.state.settings.mode;
+    if selected != mode {
+        cx.replace_view(home_view(selected, get_instant));
+    }
+    selected != mode
+}
 
+/// A second press of button A within this time after the first switches
+/// the unit instead of taring, so the tare waits for it.
+const DOUBLE_PRESS_TIME: Duration = Duration::millis(400);
+
+/// The main screen of [`Mode::Normal`].
+///
+/// Button A tares, button B starts and stops the stopwatch. If enabled in
+/// the settings, the tare also arms a [`PourDetector`] that starts and stops
+/// the stopwatch instead. A double press on button A selects the next unit
+/// without taring, so a single press tares after [`DOUBLE_PRESS_TIME`].
 pub struct Dashboard {
-    terminal: Rc<RefCell<dyn Terminal>>,
     get_instant: fn() -> Instant,
     stopwatch: Option<Stopwatch>,
+    pour_detector: Option<PourDetector>,
+    /// The press of button A that tares unless a second one follows.
+    pending_tare: Option<Instant>,
+    /// The shown weight in `unit`, see [`WeightUnit::division`].
+    quantizer: Quantizer,
+    unit: WeightUnit,
 }
 
 impl Dashboard {
-    pub fn new(terminal: Rc<RefCell<dyn Terminal>>, get_instant: fn() -> Instant) -> Self {
+    pub fn new(get_instant: fn() -> Instant) -> Self {
         Self {
-            terminal,
             get_instant,
             stopwatch: None,
+            pour_detector: None,
+            pending_tare: None,
+            quantizer: Quantizer::new(Settings::default().division()),
+            unit: WeightUnit::Grams,
         }
     }
 
-    fn handle_input(
-        &mut self,
-        e: &AppMessage,
-        push: &mut dyn FnMut(AppMessage),
-    ) -> MessageProcessingStatus {
-        if let AppMessage::InputEvent(e) = e {
-            match e {
-                InputEvent::ButtonA(e) => {
-                    match e {
-                        ButtonEvent::Press => push(AppMessage::Tare),
-                        ButtonEvent::LongPress => push(AppMessage::Calibrate),
-                    }
-                    MessageProcessingStatus::Processed
-                }
-                InputEvent::ButtonB(e) => match e {
-                    ButtonEvent::Press => {
-                        if let Some(stopwatch) = self.stopwatch.as_mut() && stopwatch.is_running() {
-                            stopwatch.stop();
-                        } else {
-                            self.stopwatch = Some(Stopwatch::new(self.get_instant));
-                        }
-                        MessageProcessingStatus::Processed
-                    }
-                    ButtonEvent::LongPress => MessageProcessingStatus::Ignored,
-                },
-            }
+    fn tare_or_switch_unit(&mut self, cx: &mut ViewContext) {
+        // Tare for an earlier press that can no longer be the first of two
+        self.finish_tare(cx);
+        if self.pending_tare.take().is_some() {
+            let mut settings = cx.state.settings;
+            settings.unit = settings.unit.next();
+            cx.send(AppMessage::ChangeSettings(settings));
+            cx.send(AppMessage::SaveSettings);
         } else {
-            MessageProcessingStatus::Ignored
+            self.pending_tare = Some((self.get_instant)());
         }
     }
 
-    fn render(&mut self, cx: &mut AppContext) -> core::fmt::Result {
-        let mut terminal = self.terminal.borrow_mut();
-        terminal.set_position(0, 0)?;
-        terminal.write_fmt(format_args!(
-            "\nWEIGHT: {:<8.2}\n",
-            Self::clamp_inf(cx.state.weight, 9999.0)
-        ))?;
-        terminal.write_fmt(format_args!(
-            "\n  TIME:{}\n",
-            Self::format_duration(
-                self.stopwatch
-                    .as_ref()
-                    .map_or_else(|| Duration::from_ticks(0), |w| w.read())
-            ),
-        ))
+    /// Tare once no second press can follow.
+    fn finish_tare(&mut self, cx: &mut ViewContext) {
+        let now = (self.get_instant)();
+        if !self
+            .pending_tare
+            .is_some_and(|t| now - t >= DOUBLE_PRESS_TIME)
+        {
+            return;
+        }
+        self.pending_tare = None;
+        cx.send(AppMessage::Tare);
+        self.pour_detector = cx.state.settings.pour_settle_time().map(|t| {
+            self.stopwatch = None;
+            PourDetector::new(PourDetection::new(t))
+        });
     }
 
-    fn clamp_inf(x: f32, abs_max: f32) -> f32 {
-        if fabsf(x) > abs_max {
-            if x >= 0.0 {
-                f32::INFINITY
-            } else {
-                f32::NEG_INFINITY
+    fn detect_pour(&mut self, state: &AppState) {
+        let Some(detector) = self.pour_detector.as_mut() else {
+            return;
+        };
+        let now = (self.get_instant)();
+        match detector.update(now, state.weight, state.flow_rate, state.is_stable) {
+            Some(PourEvent::Started) => self.stopwatch = Some(Stopwatch::new(self.get_instant)),
+            Some(PourEvent::Stopped(since)) => {
+                if let Some(stopwatch) = self.stopwatch.as_mut() {
+                    stopwatch.stop_at(since);
+                }
+                self.pour_detector = None;
             }
-        } else {
-            x
+            None => {}
         }
     }
 
@@ -97,22 +148,88 @@ impl Dashboard {
     }
 }
 
-impl Task<AppContext> for Dashboard {
-    fn run(&mut self, cx: &mut AppContext) -> TaskStatus {
-        cx.mq.process(|m, push| self.handle_input(m, push));
-        self.render(cx).unwrap();
-        TaskStatus::Pending
+impl View for Dashboard {
+    fn handle_message(&mut self, m: &AppMessage, cx: &mut ViewContext) -> MessageProcessingStatus {
+        if let AppMessage::InputEvent(e) = m {
+            match e {
+                InputEvent::ButtonA(ButtonEvent::Press) => self.tare_or_switch_unit(cx),
+                InputEvent::ButtonB(ButtonEvent::Press) => {
+                    self.pour_detector = None;
+                    if let Some(stopwatch) = self.stopwatch.as_mut() && stopwatch.is_running() {
+                        stopwatch.stop();
+                    } else {
+                        self.stopwatch = Some(Stopwatch::new(self.get_instant));
+                    }
+                }
+                e => handle_long_press(e, cx),
+            }
+            MessageProcessingStatus::Processed
+        } else {
+            MessageProcessingStatus::Ignored
+        }
+    }
+
+    fn render(&mut self, terminal: &mut dyn Terminal, state: &AppState) -> core::fmt::Result {
+        terminal.set_position(0, 0)?;
+        terminal.write_fmt(format_args!(
+            "{:<8}\n{:>6}:{}{:<8}\n",
+            state.profile_names[state.active_profile].as_str(),
+            state.settings.unit.readout_symbol(),
+            if state.is_stable { '*' } else { ' ' },
+            self.unit
+                .format(self.quantizer.value(), self.quantizer.division()),
+        ))?;
+        // The stopwatch and the flow rate share a row, 8 columns each
+        let unit = state.settings.unit;
+        let time = if self.pour_detector.as_ref().is_some_and(|d| d.is_armed()) {
+            "ARMED".into()
+        } else {
+            Self::format_duration(
+                self.stopwatch
+                    .as_ref()
+                    .map_or_else(|| Duration::from_ticks(0), |w| w.read()),
+            )
+        };
+        terminal.write_fmt(format_args!(
+            "\n{:>8}{:>3$.1}{}/s\n",
+            time,
+            clamp_inf(unit.convert(state.flow_rate), 99.9),
+            unit.symbol(),
+            6 - unit.symbol().len(),
+        ))?;
+        match state.dose {
+            Some(dose) => terminal.write_fmt(format_args!(
+                "\nRATIO{:>11}",
+                format_ratio(state.weight, dose)
+            )),
+            None => terminal.write_fmt(format_args!("\n{:16}", "")),
+        }
+    }
+
+    fn update(&mut self, cx: &mut ViewContext) {
+        if switch_mode(Mode::Normal, self.get_instant, cx) {
+            return;
+        }
+        self.finish_tare(cx);
+        self.detect_pour(cx.state);
+        let unit = cx.state.settings.unit;
+        let division = unit.division(cx.state.settings.division());
+        if self.unit != unit || self.quantizer.division() != division {
+            self.quantizer = Quantizer::new(division);
+            self.unit = unit;
+        }
+        self.quantizer.update(unit.convert(cx.state.weight));
     }
 }
 
-struct Stopwatch {
+pub(crate) struct Stopwatch {
     start: Instant,
     end: Option<Instant>,
     get_instant: fn() -> Instant,
 }
 
 impl Stopwatch {
-    fn new(get_instant: fn() -> Instant) -> Self {
+    pub(crate) fn new(get_instant: fn() -> Instant) -> Self {
         Self {
             start: get_instant(),
             end: None,
@@ -120,18 +237,229 @@ impl Stopwatch {
         }
     }
 
-    fn stop(&mut self) {
+    pub(crate) fn stop(&mut self) {
+        self.stop_at((self.get_instant)());
+    }
+
+    /// Stop as of an earlier instant, e.g. when the stop was detected late.
+    pub(crate) fn stop_at(&mut self, end: Instant) {
         if self.end.is_none() {
-            self.end = Some((self.get_instant)());
+            self.end = Some(end.max(self.start));
         }
     }
 
-    fn is_running(&self) -> bool {
+    pub(crate) fn is_running(&self) -> bool {
         self.end.is_none()
     }
 
-    fn read(&self) -> Duration {
+    pub(crate) fn read(&self) -> Duration {
         let end = self.end.unwrap_or_else(|| (self.get_instant)());
         end - self.start
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::{AtomicU64, Ordering};
+
+    use super::*;
+    use crate::{
+        common::AppContext,
+        name::Name,
+        testing::{input, show, take_messages},
+    };
+    use stuff::run_loop::Task;
+
+    #[test]
+    fn calibration_is_opened_on_top_of_the_dashboard() {
+        let mut cx = AppContext::default();
+        let (mut stack, terminal) = show(Box::new(Dashboard::new(|| Instant::from_ticks(0))));
+
+        cx.state.weight = 12.5;
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(1), "     g: 12.5");
+
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::LongPress));
+        stack.run(&mut cx);
+        assert_eq!(stack.len(), 2);
+        assert_eq!(terminal.borrow().line(0), "CALIBRATION 1/3");
+
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::LongPress));
+        stack.run(&mut cx);
+        assert_eq!(stack.len(), 1);
+        assert_eq!(terminal.borrow().line(0), "");
+        assert_eq!(terminal.borrow().line(1), "     g: 12.5");
+    }
+
+    #[test]
+    fn weight_is_shown_in_the_selected_unit() {
+        let mut cx = AppContext::default();
+        let (mut stack, terminal) = show(Box::new(Dashboard::new(|| Instant::from_ticks(0))));
+
+        cx.state.weight = 283.5;
+        cx.state.is_stable = true;
+        cx.state.settings.unit = WeightUnit::Ounces;
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(1), "    oz:*10.00");
+
+        cx.state.settings.unit = WeightUnit::PoundsOunces;
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(1), " lb:oz:*0:10.0");
+    }
+
+    #[test]
+    fn weight_is_rounded_in_the_selected_unit() {
+        let mut cx = AppContext::default();
+        let (mut stack, terminal) = show(Box::new(Dashboard::new(|| Instant::from_ticks(0))));
+
+        // The division in grams doesn't limit the ounces
+        cx.state.settings.division_hundredths = 100;
+        cx.state.settings.unit = WeightUnit::Ounces;
+        cx.state.weight = 283.5;
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(1), "    oz: 10.00");
+
+        // 10.006 oz, closer to the next step but within the hysteresis
+        cx.state.weight = 283.67;
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(1), "    oz: 10.00");
+        // 10.009 oz
+        cx.state.weight = 283.75;
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(1), "    oz: 10.01");
+
+        cx.state.settings.unit = WeightUnit::Carats;
+        cx.state.weight = 1.02;
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(1), "    ct: 5.1");
+    }
+
+    #[test]
+    fn double_press_switches_the_unit() {
+        static NOW: AtomicU64 = AtomicU64::new(0);
+        let mut cx = AppContext::default();
+        let (mut stack, _) = show(Box::new(Dashboard::new(|| {
+            Instant::from_ticks(NOW.load(Ordering::Relaxed))
+        })));
+        let press = |cx: &mut AppContext, micros: u64| {
+            NOW.store(micros, Ordering::Relaxed);
+            input(cx, InputEvent::ButtonA(ButtonEvent::Press));
+        };
+
+        press(&mut cx, 1_000_000);
+        stack.run(&mut cx);
+        press(&mut cx, 1_300_000);
+        stack.run(&mut cx);
+        NOW.store(2_000_000, Ordering::Relaxed);
+        stack.run(&mut cx);
+        let settings = Settings {
+            unit: WeightUnit::Ounces,
+            ..Default::default()
+        };
+        // Without a tare
+        assert_eq!(
+            take_messages(&mut cx),
+            [
+                AppMessage::ChangeSettings(settings),
+                AppMessage::SaveSettings,
+            ]
+        );
+
+        // A single press tares once the second one can't follow
+        press(&mut cx, 3_000_000);
+        stack.run(&mut cx);
+        NOW.store(3_399_999, Ordering::Relaxed);
+        stack.run(&mut cx);
+        assert_eq!(take_messages(&mut cx), []);
+        NOW.store(3_400_000, Ordering::Relaxed);
+        stack.run(&mut cx);
+        assert_eq!(take_messages(&mut cx), [AppMessage::Tare]);
+
+        // As do two slow presses
+        press(&mut cx, 4_000_000);
+        stack.run(&mut cx);
+        press(&mut cx, 4_500_000);
+        stack.run(&mut cx);
+        NOW.store(5_000_000, Ordering::Relaxed);
+        stack.run(&mut cx);
+        assert_eq!(take_messages(&mut cx), [AppMessage::Tare, AppMessage::Tare]);
+    }
+
+    #[test]
+    fn active_profile_is_shown() {
+        let mut cx = AppContext::default();
+        let (mut stack, terminal) = show(Box::new(Dashboard::new(|| Instant::from_ticks(0))));
+
+        cx.state.profile_names = [
+            Name::from_bytes(*b"ESPRESSO").unwrap(),
+            Name::from_bytes(*b"KITCHEN ").unwrap(),
+            Name::default(),
+        ];
+        cx.state.active_profile = 1;
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(0), "KITCHEN");
+    }
+
+    #[test]
+    fn stopwatch_is_started_and_stopped_by_pouring() {
+        static NOW: AtomicU64 = AtomicU64::new(0);
+        let mut cx = AppContext::default();
+        let (mut stack, terminal) = show(Box::new(Dashboard::new(|| {
+            Instant::from_ticks(NOW.load(Ordering::Relaxed))
+        })));
+
+        cx.state.settings.pour_settle_seconds = 3;
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::Press));
+        stack.run(&mut cx);
+        NOW.store(500_000, Ordering::Relaxed);
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(3), "   ARMED  0.0g/s");
+
+        NOW.store(1_000_000, Ordering::Relaxed);
+        cx.state.weight = 10.0;
+        cx.state.flow_rate = 5.0;
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(3), " 0:00.00  5.0g/s");
+
+        cx.state.flow_rate = 0.0;
+        cx.state.is_stable = true;
+        NOW.store(2_000_000, Ordering::Relaxed);
+        stack.run(&mut cx);
+        NOW.store(5_000_000, Ordering::Relaxed);
+        stack.run(&mut cx);
+        NOW.store(9_000_000, Ordering::Relaxed);
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(3), " 0:01.00  0.0g/s");
+    }
+
+    #[test]
+    fn brew_ratio_is_shown_with_a_dose() {
+        let mut cx = AppContext::default();
+        let (mut stack, terminal) = show(Box::new(Dashboard::new(|| Instant::from_ticks(0))));
+
+        cx.state.weight = 284.4;
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(5), "");
+        cx.state.dose = Some(18.0);
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(5), "RATIO     1:15.8");
+    }
+
+    #[test]
+    fn flow_rate_is_shown_beside_the_stopwatch() {
+        let mut cx = AppContext::default();
+        let (mut stack, terminal) = show(Box::new(Dashboard::new(|| Instant::from_ticks(0))));
+
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(3), " 0:00.00  0.0g/s");
+
+        cx.state.flow_rate = 12.34;
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(3), " 0:00.00 12.3g/s");
+
+        cx.state.flow_rate = 28.35;
+        cx.state.settings.unit = WeightUnit::Ounces;
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(3), " 0:00.00 1.0oz/s");
+    }
+}
@@ -0,0 +1,242 @@
+use alloc::{format, string::String};
+
+use crate::{
+    button::ButtonEvent,
+    common::{AppMessage, AppState, InputEvent},
+    entry::{Step, Tenths},
+    terminal::Terminal,
+    view_stack::{View, ViewContext},
+};
+use stuff::mq::MessageProcessingStatus;
+
+/// The dose to start entering from if there is none, in grams.
+const DEFAULT_DOSE: f32 = 18.0;
+/// In tenths of a gram.
+const MAX_DOSE: u16 = 9999;
+
+/// The brew ratio of the weight to the dose, e.g. `1:15.8`.
+pub fn format_ratio(weight: f32, dose: f32) -> String {
+    format!("1:{:.1}", weight / dose)
+}
+
+fn to_tenths(grams: f32) -> Tenths {
+    Tenths::from_grams(grams, 0, MAX_DOSE)
+}
+
+/// The captured weight as a dose that can be edited in [`DoseMenu`], none
+/// if it's not positive.
+pub fn captured_dose(weight: f32) -> Option<f32> {
+    let dose = to_tenths(weight);
+    (dose.get() > 0).then(|| dose.grams())
+}
+
+/// Dose menu.
+///
+/// Buttons A and B change the dose, a long press on button A captures
+/// the current weight as the dose once it's stable, a long press on
+/// button B cancels that. Otherwise a long press on button B sets the dose
+/// and closes the menu; a zero dose clears it.
+pub struct DoseMenu {
+    initial: Option<Tenths>,
+    dose: Tenths,
+    is_capturing: bool,
+}
+
+impl DoseMenu {
+    pub fn new(dose: Option<f32>) -> Self {
+        let dose = dose.map(to_tenths);
+        Self {
+            initial: dose,
+            dose: dose.unwrap_or(to_tenths(DEFAULT_DOSE)),
+            is_capturing: false,
+        }
+    }
+
+    fn handle_input(&mut self, e: &InputEvent, cx: &mut ViewContext) {
+        match e {
+            InputEvent::ButtonA(ButtonEvent::Press) => self.dose.change(Step::FINEST, true),
+            InputEvent::ButtonB(ButtonEvent::Press) => self.dose.change(Step::FINEST, false),
+            InputEvent::ButtonA(ButtonEvent::LongPress) => {
+                cx.send(AppMessage::CaptureDose);
+                self.is_capturing = true;
+            }
+            InputEvent::ButtonB(ButtonEvent::LongPress) => {
+                let dose = (self.dose.get() > 0).then_some(self.dose);
+                if dose != self.initial {
+                    cx.send(AppMessage::SetDose(dose.map(|d| d.grams())));
+                }
+                cx.pop_view();
+            }
+        }
+    }
+}
+
+impl View for DoseMenu {
+    fn handle_message(&mut self, m: &AppMessage, cx: &mut ViewContext) -> MessageProcessingStatus {
+        match m {
+            AppMessage::InputEvent(InputEvent::ButtonB(ButtonEvent::LongPress))
+                if self.is_capturing =>
+            {
+                cx.send(AppMessage::CancelCapture);
+                self.is_capturing = false;
+                MessageProcessingStatus::Processed
+            }
+            AppMessage::InputEvent(e) => {
+                if !self.is_capturing {
+                    self.handle_input(e, cx);
+                }
+                MessageProcessingStatus::Processed
+            }
+            AppMessage::Captured if self.is_capturing => {
+                // Already set
+                self.initial = cx.state.dose.map(to_tenths);
+                self.dose = self.initial.unwrap_or(self.dose);
+                self.is_capturing = false;
+                MessageProcessingStatus::Processed
+            }
+            AppMessage::CaptureFailed if self.is_capturing => {
+                self.is_capturing = false;
+                MessageProcessingStatus::Processed
+            }
+            _ => MessageProcessingStatus::Ignored,
+        }
+    }
+
+    fn render(&mut self, terminal: &mut dyn Terminal, state: &AppState) -> core::fmt::Result {
+        terminal.set_position(0, 0)?;
+        terminal.write_str("DOSE\n\n")?;
+        if self.is_capturing {
+            terminal.write_str("HOLD STILL...   \n")?;
+        } else if self.dose.get() > 0 {
+            terminal.write_fmt(format_args!("DOSE    {:>6.1} g\n", self.dose.grams()))?;
+        } else {
+            terminal.write_fmt(format_args!("DOSE    {:>8}\n", "OFF"))?;
+        }
+        terminal.write_fmt(format_args!("WEIGHT  {:>6.1} g\n\n", state.weight))?;
+        if self.is_capturing {
+            terminal.write_fmt(format_args!("{:16}\n{:16}\nHOLD B: CANCEL", "", ""))
+        } else {
+            terminal.write_str("A/B: +/-\nHOLD A: CAPTURE\nHOLD B: DONE  ")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+
+    use super::*;
+    use crate::{
+        common::AppContext,
+        testing::{input, show, take_messages},
+    };
+    use stuff::run_loop::{Task, TaskStatus};
+
+    #[test]
+    fn dose_is_entered() {
+        let mut cx = AppContext::default();
+        let (mut stack, terminal) = show(Box::new(DoseMenu::new(None)));
+
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(2), "DOSE      18.0 g");
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::Press));
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::Press));
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(2), "DOSE      18.2 g");
+
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::LongPress));
+        assert!(matches!(stack.run(&mut cx), TaskStatus::Done));
+        assert_eq!(take_messages(&mut cx), [AppMessage::SetDose(Some(18.2))]);
+    }
+
+    #[test]
+    fn zero_dose_clears_it() {
+        let mut cx = AppContext::default();
+        let (mut stack, terminal) = show(Box::new(DoseMenu::new(Some(0.2))));
+
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::Press));
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::Press));
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::Press));
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(2), "DOSE         OFF");
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::LongPress));
+        stack.run(&mut cx);
+        assert_eq!(take_messages(&mut cx), [AppMessage::SetDose(None)]);
+    }
+
+    #[test]
+    fn dose_is_captured() {
+        let mut cx = AppContext::default();
+        let (mut stack, terminal) = show(Box::new(DoseMenu::new(None)));
+
+        cx.state.weight = 17.9;
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::LongPress));
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(2), "HOLD STILL...");
+        assert_eq!(terminal.borrow().line(3), "WEIGHT    17.9 g");
+        assert_eq!(take_messages(&mut cx), [AppMessage::CaptureDose]);
+
+        // What the main loop does
+        cx.state.dose = Some(17.9);
+        cx.mq.push(AppMessage::Captured);
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(2), "DOSE      17.9 g");
+
+        // Already set
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::LongPress));
+        stack.run(&mut cx);
+        assert_eq!(take_messages(&mut cx), []);
+    }
+
+    #[test]
+    fn captured_dose_is_in_the_range_of_the_menu() {
+        assert_eq!(captured_dose(17.94), Some(17.9));
+        assert_eq!(captured_dose(1_234.5), Some(999.9));
+        assert_eq!(captured_dose(0.04), None);
+        assert_eq!(captured_dose(-3.0), None);
+    }
+
+    #[test]
+    fn capture_can_be_cancelled() {
+        let mut cx = AppContext::default();
+        let (mut stack, terminal) = show(Box::new(DoseMenu::new(Some(18.0))));
+
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::LongPress));
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(6), "");
+        assert_eq!(terminal.borrow().line(7), "HOLD B: CANCEL");
+        // Other input is ignored until the scale responds
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::Press));
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::LongPress));
+        assert!(matches!(stack.run(&mut cx), TaskStatus::Pending));
+        assert_eq!(
+            take_messages(&mut cx),
+            [AppMessage::CaptureDose, AppMessage::CancelCapture]
+        );
+        assert_eq!(terminal.borrow().line(2), "DOSE      18.0 g");
+        assert_eq!(terminal.borrow().line(6), "HOLD A: CAPTURE");
+        assert_eq!(terminal.borrow().line(7), "HOLD B: DONE");
+
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::LongPress));
+        assert!(matches!(stack.run(&mut cx), TaskStatus::Done));
+        assert_eq!(take_messages(&mut cx), []);
+    }
+
+    #[test]
+    fn failed_capture_keeps_the_dose() {
+        let mut cx = AppContext::default();
+        let (mut stack, terminal) = show(Box::new(DoseMenu::new(Some(18.0))));
+
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::LongPress));
+        stack.run(&mut cx);
+        assert_eq!(take_messages(&mut cx), [AppMessage::CaptureDose]);
+        cx.mq.push(AppMessage::CaptureFailed);
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(2), "DOSE      18.0 g");
+    }
+
+    #[test]
+    fn ratio_is_formatted() {
+        assert_eq!(format_ratio(284.4, 18.0), "1:15.8");
+    }
+}
@@ -0,0 +1,113 @@
+//! Entering weights with the buttons, in the menus.
+
+/// The steps a weight can be changed by, in tenths of a gram.
+const STEPS: [u16; 4] = [1, 10, 100, 1_000];
+
+/// A weight in tenths of a gram, the resolution it's entered with, kept
+/// within a range.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Tenths {
+    value: u16,
+    min: u16,
+    max: u16,
+}
+
+impl Tenths {
+    /// The weight rounded to the nearest tenth of a gram in the range,
+    /// which is in tenths of a gram too.
+    pub fn from_grams(grams: f32, min: u16, max: u16) -> Self {
+        let value = libm::roundf(grams * 10.0).clamp(min as f32, max as f32) as u16;
+        Self {
+            // A NaN weight is converted to zero
+            value: value.max(min),
+            min,
+            max,
+        }
+    }
+
+    pub fn get(&self) -> u16 {
+        self.value
+    }
+
+    pub fn grams(&self) -> f32 {
+        self.value as f32 / 10.0
+    }
+
+    /// Add (or subtract) the step, staying in the range.
+    pub fn change(&mut self, step: Step, forward: bool) {
+        self.value = if forward {
+            self.value.saturating_add(step.tenths())
+        } else {
+            self.value.saturating_sub(step.tenths())
+        }
+        .clamp(self.min, self.max);
+    }
+}
+
+/// One of the steps a weight can be changed by, from 0.1 g to 100 g.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Step(usize);
+
+impl Default for Step {
+    /// 1 g.
+    fn default() -> Self {
+        Self(1)
+    }
+}
+
+impl Step {
+    /// 0.1 g.
+    pub const FINEST: Self = Self(0);
+
+    pub fn tenths(&self) -> u16 {
+        STEPS[self.0]
+    }
+
+    pub fn grams(&self) -> f32 {
+        self.tenths() as f32 / 10.0
+    }
+
+    /// Select the next (or the previous) step, wrapping around.
+    pub fn change(&mut self, forward: bool) {
+        let n = STEPS.len();
+        self.0 = (self.0 + if forward { 1 } else { n - 1 }) % n;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weight_is_kept_in_range() {
+        let mut t = Tenths::from_grams(18.04, 1, 500);
+        assert_eq!(t.get(), 180);
+        assert_eq!(t.grams(), 18.0);
+        let mut step = Step::default();
+        step.change(true);
+        step.change(true);
+        assert_eq!(step.grams(), 100.0);
+        t.change(step, true);
+        t.change(step, true);
+        assert_eq!(t.get(), 500);
+        t.change(step, false);
+        t.change(step, false);
+        t.change(step, false);
+        assert_eq!(t.get(), 1);
+
+        assert_eq!(Tenths::from_grams(-5.0, 1, 500).get(), 1);
+        assert_eq!(Tenths::from_grams(f32::NAN, 0, 500).get(), 0);
+        assert_eq!(Tenths::from_grams(1e9, 0, 500).get(), 500);
+    }
+
+    #[test]
+    fn steps_wrap_around() {
+        let mut step = Step::default();
+        step.change(false);
+        assert_eq!(step.tenths(), 1);
+        step.change(false);
+        assert_eq!(step.tenths(), 1_000);
+        step.change(true);
+        assert_eq!(step.tenths(), 1);
+    }
+}
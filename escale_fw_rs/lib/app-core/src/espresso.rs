@@ -0,0 +1,261 @@
+use alloc::format;
+
+use crate::{
+    button::ButtonEvent,
+    common::{AppMessage, AppState, Duration, InputEvent, Instant},
+    dashboard::{handle_long_press, switch_mode, Stopwatch},
+    pour::{PourDetection, PourDetector, PourEvent},
+    settings::Mode,
+    terminal::Terminal,
+    units::{clamp_inf, weight_row},
+    view_stack::{View, ViewContext},
+};
+use stuff::mq::MessageProcessingStatus;
+
+/// The first drops are a small step after the tare, the flow has stopped
+/// once the weight has been stable for a moment.
+const SHOT_DETECTION: PourDetection = PourDetection {
+    threshold: 0.3,
+    rate: 0.1,
+    settle_time: Duration::millis(1_500),
+};
+
+/// The main screen of [`Mode::Espresso`].
+///
+/// Button A tares and arms the shot timer: it starts when the first drops hit
+/// the cup and stops when the flow stops. Button B stops it manually. The
+/// summary of the last shot stays on the screen until the next tare. The long
+/// presses are the same as on the [`crate::dashboard::Dashboard`].
+pub struct EspressoView {
+    get_instant: fn() -> Instant,
+    detector: Option<PourDetector>,
+    shot: Option<Shot>,
+}
+
+struct Shot {
+    stopwatch: Stopwatch,
+    /// The weight in the cup when the shot stopped, in grams.
+    yield_weight: Option<f32>,
+}
+
+impl EspressoView {
+    pub fn new(get_instant: fn() -> Instant) -> Self {
+        Self {
+            get_instant,
+            detector: None,
+            shot: None,
+        }
+    }
+
+    fn stop(&mut self, end: Instant, weight: f32) {
+        if let Some(shot) = self.shot.as_mut() && shot.yield_weight.is_none() {
+            shot.stopwatch.stop_at(end);
+            shot.yield_weight = Some(weight);
+        }
+        self.detector = None;
+    }
+
+    fn status(&self) -> &'static str {
+        match (&self.detector, &self.shot) {
+            (Some(d), _) if d.is_armed() => "READY",
+            (_, Some(shot)) if shot.yield_weight.is_none() => "SHOT",
+            (_, Some(_)) => "DONE",
+            (_, None) => "",
+        }
+    }
+}
+
+impl View for EspressoView {
+    fn handle_message(&mut self, m: &AppMessage, cx: &mut ViewContext) -> MessageProcessingStatus {
+        if let AppMessage::InputEvent(e) = m {
+            match e {
+                InputEvent::ButtonA(ButtonEvent::Press) => {
+                    cx.send(AppMessage::Tare);
+                    self.shot = None;
+                    self.detector = Some(PourDetector::new(SHOT_DETECTION));
+                }
+                InputEvent::ButtonB(ButtonEvent::Press) => {
+                    self.stop((self.get_instant)(), cx.state.weight)
+                }
+                e => handle_long_press(e, cx),
+            }
+            MessageProcessingStatus::Processed
+        } else {
+            MessageProcessingStatus::Ignored
+        }
+    }
+
+    fn render(&mut self, terminal: &mut dyn Terminal, state: &AppState) -> core::fmt::Result {
+        let unit = state.settings.unit;
+        let (time, weight, flow_label, flow) = match &self.shot {
+            Some(Shot {
+                stopwatch,
+                yield_weight: Some(weight),
+            }) => {
+                let time = stopwatch.read().to_millis() as f32 / 1000.0;
+                let average = if time > 0.0 { weight / time } else { 0.0 };
+                (time, *weight, "AVG", average)
+            }
+            shot => (
+                shot.as_ref()
+                    .map_or(0.0, |s| s.stopwatch.read().to_millis() as f32 / 1000.0),
+                state.weight,
+                "FLOW",
+                state.flow_rate,
+            ),
+        };
+        terminal.set_position(0, 0)?;
+        terminal.write_fmt(format_args!("ESPRESSO{:>8}\n\n", self.status()))?;
+        // The time and the flow rate are laid out as the weight rows
+        let row = |label: &str, value: f32, suffix: &str| {
+            format!(
+                "{label:<6}{:>1$.1} {suffix}",
+                clamp_inf(value, 999.9),
+                9 - suffix.len(),
+            )
+        };
+        let division = unit.division(state.settings.division());
+        let flow_symbol = format!("{}/s", unit.symbol());
+        terminal.write_fmt(format_args!(
+            "{}\n{}\n{}\n",
+            row("TIME", time, "s"),
+            weight_row("YIELD", weight, unit, division),
+            row(flow_label, unit.convert(flow), &flow_symbol),
+        ))
+    }
+
+    fn update(&mut self, cx: &mut ViewContext) {
+        if switch_mode(Mode::Espresso, self.get_instant, cx) {
+            return;
+        }
+        let Some(detector) = self.detector.as_mut() else {
+            return;
+        };
+        let state = cx.state;
+        let now = (self.get_instant)();
+        match detector.update(now, state.weight, state.flow_rate, state.is_stable) {
+            Some(PourEvent::Started) => {
+                self.shot = Some(Shot {
+                    stopwatch: Stopwatch::new(self.get_instant),
+                    yield_weight: None,
+                })
+            }
+            Some(PourEvent::Stopped(end)) => self.stop(end, state.weight),
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        cell::RefCell,
+        sync::atomic::{AtomicU64, Ordering},
+    };
+
+    use super::*;
+    use crate::{
+        common::AppContext,
+        dashboard::home_view,
+        testing::{input, show, FakeTerminal},
+    };
+    use stuff::run_loop::Task;
+
+    fn lines(terminal: &RefCell<FakeTerminal>) -> [String; 5] {
+        core::array::from_fn(|i| terminal.borrow().line(i))
+    }
+
+    #[test]
+    fn shot_is_timed_from_the_first_drops_until_the_flow_stops() {
+        static NOW: AtomicU64 = AtomicU64::new(0);
+        let set_seconds = |s: f32| NOW.store((s * 1e6) as u64, Ordering::Relaxed);
+        let mut cx = AppContext::default();
+        cx.state.settings.mode = Mode::Espresso;
+        let (mut stack, terminal) = show(Box::new(EspressoView::new(|| {
+            Instant::from_ticks(NOW.load(Ordering::Relaxed))
+        })));
+
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::Press));
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(0), "ESPRESSO   READY");
+
+        // The first drops
+        set_seconds(5.0);
+        cx.state.weight = 0.4;
+        cx.state.flow_rate = 0.4;
+        stack.run(&mut cx);
+        set_seconds(30.0);
+        cx.state.weight = 36.0;
+        cx.state.flow_rate = 2.0;
+        stack.run(&mut cx);
+        assert_eq!(
+            lines(&terminal),
+            [
+                "ESPRESSO    SHOT",
+                "",
+                "TIME      25.0 s",
+                "YIELD     36.0 g",
+                "FLOW     2.0 g/s",
+            ]
+        );
+
+        // The flow stops
+        cx.state.flow_rate = 0.0;
+        cx.state.is_stable = true;
+        set_seconds(31.0);
+        stack.run(&mut cx);
+        set_seconds(33.0);
+        stack.run(&mut cx);
+        // The summary stays when the cup is taken away
+        cx.state.weight = -200.0;
+        cx.state.is_stable = false;
+        stack.run(&mut cx);
+        assert_eq!(
+            lines(&terminal),
+            [
+                "ESPRESSO    DONE",
+                "",
+                "TIME      26.0 s",
+                "YIELD     36.0 g",
+                "AVG      1.4 g/s",
+            ]
+        );
+
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::Press));
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(0), "ESPRESSO   READY");
+        assert_eq!(terminal.borrow().line(2), "TIME       0.0 s");
+    }
+
+    #[test]
+    fn shot_is_stopped_manually() {
+        let mut cx = AppContext::default();
+        cx.state.settings.mode = Mode::Espresso;
+        let (mut stack, terminal) = show(Box::new(EspressoView::new(|| Instant::from_ticks(0))));
+
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::Press));
+        stack.run(&mut cx);
+        cx.state.weight = 1.0;
+        cx.state.flow_rate = 1.0;
+        stack.run(&mut cx);
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::Press));
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(0), "ESPRESSO    DONE");
+    }
+
+    #[test]
+    fn mode_is_switched_from_the_settings() {
+        let mut cx = AppContext::default();
+        let (mut stack, terminal) = show(home_view(Mode::Normal, || Instant::from_ticks(0)));
+
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(3), " 0:00.00  0.0g/s");
+        cx.state.settings.mode = Mode::Espresso;
+        stack.run(&mut cx);
+        assert_eq!(stack.len(), 1);
+        assert_eq!(terminal.borrow().line(0), "ESPRESSO");
+        cx.state.settings.mode = Mode::Normal;
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(3), " 0:00.00  0.0g/s");
+    }
+}
@@ -0,0 +1,138 @@
+use libm::fabsf;
+use stuff::simple_ring::SimpleRing;
+
+use crate::common::Instant;
+
+/// The weight of a new slope in the smoothed flow rate.
+const SMOOTHING: f32 = 0.2;
+
+/// A flow rate (in g/s) below which the flow is considered stopped,
+/// so that the noise of a resting weight doesn't show up as a flow.
+const DEADBAND: f32 = 0.1;
+
+/// Estimates the flow rate as the slope of a linear regression over
+/// the last `N` time-stamped weights, smoothed exponentially.
+pub struct FlowRate<const N: usize>
+where
+    [(); N - 1]:,
+{
+    samples: SimpleRing<Option<(Instant, f32)>, N>,
+    rate: f32,
+}
+
+impl<const N: usize> Default for FlowRate<N>
+where
+    [(); N - 1]:,
+{
+    fn default() -> Self {
+        Self {
+            samples: SimpleRing::default(),
+            rate: 0.0,
+        }
+    }
+}
+
+impl<const N: usize> FlowRate<N>
+where
+    [(); N - 1]:,
+{
+    /// Add a filtered weight, in grams.
+    pub fn push(&mut self, instant: Instant, weight: f32) {
+        self.samples.push(Some((instant, weight)));
+        if let Some(slope) = self.slope() {
+            self.rate += SMOOTHING * (slope - self.rate);
+        }
+    }
+
+    /// The flow rate in g/s.
+    pub fn read(&self) -> f32 {
+        if fabsf(self.rate) < DEADBAND {
+            0.0
+        } else {
+            self.rate
+        }
+    }
+
+    /// Forget the history, e.g. after the weight has jumped because of a tare.
+    pub fn reset(&mut self) {
+        self.samples.reset(None);
+        self.rate = 0.0;
+    }
+
+    fn slope(&self) -> Option<f32> {
+        let samples = self.samples.iter().flatten();
+        let &(start, _) = samples.clone().next()?;
+        // Seconds since the oldest sample keep the sums small enough for f32
+        let points = samples.map(|&(t, w)| ((t - start).to_micros() as f32 / 1e6, w));
+        let (mut n, mut sum_t, mut sum_w) = (0.0, 0.0, 0.0);
+        for (t, w) in points.clone() {
+            n += 1.0;
+            sum_t += t;
+            sum_w += w;
+        }
+        let (mean_t, mean_w) = (sum_t / n, sum_w / n);
+        let (mut covariance, mut variance) = (0.0, 0.0);
+        for (t, w) in points {
+            covariance += (t - mean_t) * (w - mean_w);
+            variance += (t - mean_t) * (t - mean_t);
+        }
+        (variance > 0.0).then(|| covariance / variance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Samples at 20 SPS.
+    fn instant(i: u64) -> Instant {
+        Instant::from_ticks(i * 50_000)
+    }
+
+    #[test]
+    fn steady_flow_is_measured() {
+        let mut flow = FlowRate::<20>::default();
+        assert_eq!(flow.read(), 0.0);
+        for i in 0..100 {
+            flow.push(instant(i), 100.0 + i as f32 * 0.25);
+        }
+        assert!((flow.read() - 5.0).abs() < 0.01, "{}", flow.read());
+    }
+
+    #[test]
+    fn noise_during_a_pause_is_not_a_flow() {
+        let mut flow = FlowRate::<20>::default();
+        for i in 0..100 {
+            flow.push(instant(i), i as f32 * 0.25);
+        }
+        for i in 100..200 {
+            let noise = if i % 3 == 0 { 0.02 } else { -0.01 };
+            flow.push(instant(i), 25.0 + noise);
+            if i >= 140 {
+                assert_eq!(flow.read(), 0.0, "{i}");
+            }
+        }
+    }
+
+    #[test]
+    fn spike_is_smoothed() {
+        let mut flow = FlowRate::<20>::default();
+        for i in 0..40 {
+            flow.push(instant(i), 10.0);
+        }
+        flow.push(instant(40), 12.0);
+        assert!(flow.read() < 1.0, "{}", flow.read());
+    }
+
+    #[test]
+    fn history_is_forgotten_on_reset() {
+        let mut flow = FlowRate::<20>::default();
+        for i in 0..40 {
+            flow.push(instant(i), i as f32);
+        }
+        flow.reset();
+        assert_eq!(flow.read(), 0.0);
+        flow.push(instant(40), 0.0);
+        assert_eq!(flow.read(), 0.0);
+    }
+}
@@ -5,9 +5,34 @@
 
 extern crate alloc;
 
+pub mod auto_off;
 pub mod button;
+pub mod calibration;
+pub mod calibration_table;
+pub mod capture;
+pub mod check;
 pub mod common;
+pub mod conf;
+pub mod count;
 pub mod dashboard;
+pub mod dose;
+pub mod entry;
+pub mod espresso;
+pub mod flow_rate;
 pub mod input_scanner;
+pub mod message;
+pub mod name;
+pub mod percent;
+pub mod pour;
+pub mod profile;
+pub mod quantizer;
+pub mod recipe;
 pub mod scale;
+pub mod scale_fir;
+pub mod settings;
+pub mod target;
 pub mod terminal;
+#[cfg(test)]
+mod testing;
+pub mod units;
+pub mod view_stack;
@@ -0,0 +1,73 @@
+use alloc::string::String;
+
+use crate::{
+    common::{AppMessage, AppState},
+    terminal::Terminal,
+    view_stack::{View, ViewContext},
+};
+use stuff::mq::MessageProcessingStatus;
+
+/// A text message dismissed by pressing any button.
+pub struct MessageView {
+    text: String,
+    is_rendered: bool,
+}
+
+impl MessageView {
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            is_rendered: false,
+        }
+    }
+}
+
+impl View for MessageView {
+    fn handle_message(&mut self, m: &AppMessage, cx: &mut ViewContext) -> MessageProcessingStatus {
+        if let AppMessage::InputEvent(_) = m {
+            cx.pop_view();
+            MessageProcessingStatus::Processed
+        } else {
+            MessageProcessingStatus::Ignored
+        }
+    }
+
+    fn render(&mut self, terminal: &mut dyn Terminal, _: &AppState) -> core::fmt::Result {
+        if !self.is_rendered {
+            terminal.set_position(0, 2)?;
+            terminal.write_str(&self.text)?;
+            self.is_rendered = true;
+        }
+        Ok(())
+    }
+
+    fn appear(&mut self) {
+        self.is_rendered = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+
+    use super::*;
+    use crate::{
+        button::ButtonEvent,
+        common::{AppContext, InputEvent},
+        testing::{input, show},
+    };
+    use stuff::run_loop::{Task, TaskStatus};
+
+    #[test]
+    fn message_is_shown_until_a_button_is_pressed() {
+        let mut cx = AppContext::default();
+        let (mut stack, terminal) = show(Box::new(MessageView::new("CALIBRATION\nSAVED")));
+
+        assert!(matches!(stack.run(&mut cx), TaskStatus::Pending));
+        assert_eq!(terminal.borrow().line(2), "CALIBRATION");
+        assert_eq!(terminal.borrow().line(3), "SAVED");
+
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::Press));
+        assert!(matches!(stack.run(&mut cx), TaskStatus::Done));
+    }
+}
@@ -0,0 +1,68 @@
+pub const NAME_SIZE: usize = 8;
+
+/// The characters a name is made of.
+const NAME_CHARS: &[u8] = b" ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789-";
+
+/// A name of up to [`NAME_SIZE`] characters padded with spaces, e.g. of
+/// a calibration profile or a recipe.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Name([u8; NAME_SIZE]);
+
+impl Default for Name {
+    fn default() -> Self {
+        Self([b' '; NAME_SIZE])
+    }
+}
+
+impl Name {
+    /// Returns `None` if there are characters other than [`NAME_CHARS`].
+    pub fn from_bytes(bytes: [u8; NAME_SIZE]) -> Option<Self> {
+        bytes
+            .iter()
+            .all(|b| NAME_CHARS.contains(b))
+            .then_some(Self(bytes))
+    }
+
+    pub fn to_bytes(&self) -> [u8; NAME_SIZE] {
+        self.0
+    }
+
+    /// The name without the padding.
+    pub fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.0).unwrap_or_default().trim_end()
+    }
+
+    /// Select the next (or the previous) character at the position,
+    /// wrapping around.
+    pub fn change(&mut self, position: usize, forward: bool) {
+        let c = &mut self.0[position];
+        let i = NAME_CHARS.iter().position(|x| x == c).unwrap_or(0);
+        let n = NAME_CHARS.len();
+        *c = NAME_CHARS[if forward {
+            (i + 1) % n
+        } else {
+            (i + n - 1) % n
+        }];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_validated() {
+        let name = Name::from_bytes(*b"KITCHEN ").unwrap();
+        assert_eq!(name.as_str(), "KITCHEN");
+        assert_eq!(Name::from_bytes(*b"kitchen "), None);
+        assert_eq!(Name::from_bytes([0xff; NAME_SIZE]), None);
+    }
+
+    #[test]
+    fn characters_wrap_around() {
+        let mut name = Name::from_bytes(*b"A-      ").unwrap();
+        name.change(0, false);
+        name.change(1, true);
+        assert_eq!(name, Name::default());
+    }
+}
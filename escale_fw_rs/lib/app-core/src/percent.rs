@@ -0,0 +1,161 @@
+use crate::{
+    button::ButtonEvent,
+    common::{AppMessage, AppState, InputEvent, Instant},
+    dashboard::{handle_long_press, switch_mode},
+    settings::Mode,
+    terminal::Terminal,
+    units::{clamp_inf, weight_row},
+    view_stack::{View, ViewContext},
+};
+use stuff::mq::MessageProcessingStatus;
+
+/// The weight as a percentage of the reference, e.g. of the flour for
+/// baker's percentages.
+pub fn percentage(weight: f32, reference: f32) -> f32 {
+    weight / reference * 100.0
+}
+
+/// The main screen of [`Mode::Percent`].
+///
+/// Button A tares, the reference is kept. Button B takes the weight as
+/// 100 % once it's stable and positive. The long presses are the same as on the
+/// [`crate::dashboard::Dashboard`].
+pub struct PercentView {
+    get_instant: fn() -> Instant,
+    /// The weight that is 100 %, in grams.
+    reference: Option<f32>,
+    is_capturing: bool,
+}
+
+impl PercentView {
+    pub fn new(get_instant: fn() -> Instant) -> Self {
+        Self {
+            get_instant,
+            reference: None,
+            is_capturing: false,
+        }
+    }
+}
+
+impl View for PercentView {
+    fn handle_message(&mut self, m: &AppMessage, cx: &mut ViewContext) -> MessageProcessingStatus {
+        if let AppMessage::InputEvent(e) = m {
+            match e {
+                InputEvent::ButtonA(ButtonEvent::Press) => cx.send(AppMessage::Tare),
+                InputEvent::ButtonB(ButtonEvent::Press) => self.is_capturing = !self.is_capturing,
+                e => handle_long_press(e, cx),
+            }
+            MessageProcessingStatus::Processed
+        } else {
+            MessageProcessingStatus::Ignored
+        }
+    }
+
+    fn render(&mut self, terminal: &mut dyn Terminal, state: &AppState) -> core::fmt::Result {
+        let unit = state.settings.unit;
+        let division = unit.division(state.settings.division());
+        terminal.set_position(0, 0)?;
+        terminal.write_str("PERCENT\n\n")?;
+        match self.reference {
+            Some(reference) => terminal.write_fmt(format_args!(
+                "{:>14.1} %\n\n",
+                clamp_inf(percentage(state.weight, reference), 99_999.9)
+            ))?,
+            None => terminal.write_fmt(format_args!("{:16}\n\n", ""))?,
+        }
+        terminal.write_fmt(format_args!(
+            "{}\n",
+            weight_row("WEIGHT", state.weight, unit, division)
+        ))?;
+        match self.reference {
+            Some(reference) => terminal.write_fmt(format_args!(
+                "{}\n\n",
+                weight_row("100 %", reference, unit, division)
+            ))?,
+            None => terminal.write_fmt(format_args!("{:16}\n\n", ""))?,
+        }
+        let hint = if !self.is_capturing {
+            "B: SET 100 %"
+        } else if state.is_stable && state.weight <= 0.0 {
+            "ADD WEIGHT"
+        } else {
+            "HOLD STILL..."
+        };
+        terminal.write_fmt(format_args!("{hint:16}"))
+    }
+
+    fn update(&mut self, cx: &mut ViewContext) {
+        if switch_mode(Mode::Percent, self.get_instant, cx) {
+            return;
+        }
+        // A reference of no weight would make every percentage infinite, so
+        // it's captured once there's something on the scale
+        if self.is_capturing && cx.state.is_stable && cx.state.weight > 0.0 {
+            self.reference = Some(cx.state.weight);
+            self.is_capturing = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::String;
+
+    use super::*;
+    use crate::{
+        common::AppContext,
+        testing::{input, show},
+    };
+    use stuff::run_loop::Task;
+
+    #[test]
+    fn percentage_is_relative_to_the_reference() {
+        assert_eq!(percentage(650.0, 1000.0), 65.0);
+        assert_eq!(percentage(0.0, 500.0), 0.0);
+        assert_eq!(percentage(750.0, 500.0), 150.0);
+    }
+
+    #[test]
+    fn reference_is_kept_across_tares() {
+        let mut cx = AppContext::default();
+        cx.state.settings.mode = Mode::Percent;
+        let (mut stack, terminal) = show(Box::new(PercentView::new(|| Instant::from_ticks(0))));
+
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::Press));
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(2), "");
+        assert_eq!(terminal.borrow().line(7), "HOLD STILL...");
+
+        cx.state.is_stable = true;
+        stack.run(&mut cx);
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(2), "");
+        assert_eq!(terminal.borrow().line(7), "ADD WEIGHT");
+
+        cx.state.weight = 500.0;
+        stack.run(&mut cx);
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(2), "         100.0 %");
+
+        // What the main loop does on a tare
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::Press));
+        cx.state.weight = 0.0;
+        stack.run(&mut cx);
+        cx.state.weight = 325.0;
+        stack.run(&mut cx);
+        let lines: [String; 8] = core::array::from_fn(|i| terminal.borrow().line(i));
+        assert_eq!(
+            lines,
+            [
+                "PERCENT",
+                "",
+                "          65.0 %",
+                "",
+                "WEIGHT   325.0 g",
+                "100 %    500.0 g",
+                "",
+                "B: SET 100 %",
+            ]
+        );
+    }
+}
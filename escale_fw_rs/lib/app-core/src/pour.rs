@@ -0,0 +1,162 @@
+use crate::common::{Duration, Instant};
+
+/// The conditions of an automatic start and stop of the stopwatch.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PourDetection {
+    /// The weight above which pouring starts, in grams after the tare.
+    pub threshold: f32,
+    /// The flow rate needed to start, in g/s, so that a weight past the
+    /// threshold that isn't rising doesn't count as pouring, e.g. a cup
+    /// still in the readout while the tare waits for it to settle. Putting
+    /// a cup down after the tare is a fast rise, so it does count.
+    pub rate: f32,
+    /// How long the weight must stay stable for pouring to stop.
+    pub settle_time: Duration,
+}
+
+impl PourDetection {
+    pub fn new(settle_time: Duration) -> Self {
+        Self {
+            threshold: 1.0,
+            rate: 0.5,
+            settle_time,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PourEvent {
+    Started,
+    /// Pouring has stopped at the given instant, i.e. the weight has been
+    /// stable since then.
+    Stopped(Instant),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum State {
+    Armed,
+    Pouring { stable_since: Option<Instant> },
+    Done,
+}
+
+/// Detects the start and the end of pouring from the readouts.
+///
+/// The detector is created armed, e.g. after a tare. Pouring starts once the
+/// weight rises past the threshold at least at the given rate and stops once
+/// the weight has been stable for the settle time. The detector is done
+/// after that and has to be replaced to detect another pour.
+pub struct PourDetector {
+    detection: PourDetection,
+    state: State,
+}
+
+impl PourDetector {
+    pub fn new(detection: PourDetection) -> Self {
+        Self {
+            detection,
+            state: State::Armed,
+        }
+    }
+
+    /// Whether pouring hasn't started yet.
+    pub fn is_armed(&self) -> bool {
+        self.state == State::Armed
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.state == State::Done
+    }
+
+    /// Feed a readout: the weight in grams and the flow rate in g/s.
+    pub fn update(
+        &mut self,
+        now: Instant,
+        weight: f32,
+        flow_rate: f32,
+        is_stable: bool,
+    ) -> Option<PourEvent> {
+        match &mut self.state {
+            State::Armed => {
+                if weight >= self.detection.threshold && flow_rate >= self.detection.rate {
+                    self.state = State::Pouring { stable_since: None };
+                    return Some(PourEvent::Started);
+                }
+            }
+            State::Pouring { stable_since } => {
+                if !is_stable {
+                    *stable_since = None;
+                } else {
+                    let since = *stable_since.get_or_insert(now);
+                    if now - since >= self.detection.settle_time {
+                        self.state = State::Done;
+                        return Some(PourEvent::Stopped(since));
+                    }
+                }
+            }
+            State::Done => {}
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seconds(s: u64) -> Instant {
+        Instant::from_ticks(s * 1_000_000)
+    }
+
+    fn detector() -> PourDetector {
+        PourDetector::new(PourDetection::new(Duration::secs(3)))
+    }
+
+    #[test]
+    fn pouring_starts_and_stops() {
+        let mut d = detector();
+        assert!(d.is_armed());
+        assert_eq!(d.update(seconds(0), 0.0, 0.0, true), None);
+        assert_eq!(d.update(seconds(1), 0.8, 0.8, false), None);
+        assert_eq!(
+            d.update(seconds(2), 1.6, 0.8, false),
+            Some(PourEvent::Started)
+        );
+        assert!(!d.is_armed());
+        assert_eq!(d.update(seconds(3), 50.0, 10.0, false), None);
+        assert_eq!(d.update(seconds(4), 60.0, 0.0, true), None);
+        assert_eq!(d.update(seconds(6), 60.0, 0.0, true), None);
+        assert_eq!(
+            d.update(seconds(7), 60.0, 0.0, true),
+            Some(PourEvent::Stopped(seconds(4)))
+        );
+        assert!(d.is_done());
+        assert_eq!(d.update(seconds(8), 120.0, 10.0, false), None);
+    }
+
+    #[test]
+    fn slow_rise_is_not_pouring() {
+        let mut d = detector();
+        // E.g. a cup put on the scale after the tare and still settling
+        assert_eq!(d.update(seconds(0), 200.0, 0.2, false), None);
+        assert_eq!(d.update(seconds(1), 200.0, 0.0, true), None);
+        assert!(d.is_armed());
+    }
+
+    #[test]
+    fn pause_shorter_than_the_settle_time_continues_pouring() {
+        let mut d = detector();
+        assert_eq!(
+            d.update(seconds(0), 10.0, 5.0, false),
+            Some(PourEvent::Started)
+        );
+        assert_eq!(d.update(seconds(1), 40.0, 0.0, true), None);
+        assert_eq!(d.update(seconds(3), 40.0, 0.0, true), None);
+        assert_eq!(d.update(seconds(4), 60.0, 5.0, false), None);
+        assert_eq!(d.update(seconds(5), 80.0, 0.0, true), None);
+        assert_eq!(d.update(seconds(7), 80.0, 0.0, true), None);
+        assert_eq!(
+            d.update(seconds(8), 80.0, 0.0, true),
+            Some(PourEvent::Stopped(seconds(5)))
+        );
+    }
+}
@@ -0,0 +1,219 @@
+use crate::{
+    button::ButtonEvent,
+    calibration_table::CalibrationTable,
+    common::{AppMessage, AppState, InputEvent},
+    name::{Name, NAME_SIZE},
+    terminal::Terminal,
+    view_stack::{View, ViewContext},
+};
+use stuff::mq::MessageProcessingStatus;
+
+pub const PROFILE_COUNT: usize = 3;
+
+/// The calibration of a load cell (e.g. a platform of the scale).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Profile {
+    pub name: Name,
+    /// The raw readout of the empty platform, captured by the calibration.
+    /// The scale is tared instead when the profile is activated without it.
+    pub zero: Option<f32>,
+    pub unit: f32,
+    pub calibration: CalibrationTable<f32>,
+    /// The reference mass used for the last calibration, in grams.
+    pub calibration_mass: f32,
+}
+
+impl Profile {
+    pub fn new(name: Name) -> Self {
+        Self {
+            name,
+            zero: None,
+            unit: 1.0,
+            calibration: Default::default(),
+            calibration_mass: 100.0,
+        }
+    }
+}
+
+/// The default name of the profile with the given index.
+pub fn numbered_name(index: usize) -> Name {
+    let mut name = *b"PROFILE ";
+    name[NAME_SIZE - 1] = b'1' + (index % 9) as u8;
+    Name::from_bytes(name).unwrap()
+}
+
+/// Calibration profile menu.
+///
+/// Buttons A and B move between the profiles, a long press on button B
+/// activates the selected one and closes the menu. A long press on button A
+/// renames the selected profile: the buttons change the character under
+/// the cursor, a long press on button A moves to the next character and
+/// a long press on button B goes back to the profiles.
+pub struct ProfileMenu {
+    initial_names: [Name; PROFILE_COUNT],
+    names: [Name; PROFILE_COUNT],
+    active: usize,
+    index: usize,
+    /// The position of the character being changed while renaming.
+    cursor: Option<usize>,
+}
+
+impl ProfileMenu {
+    pub fn new(names: [Name; PROFILE_COUNT], active: usize) -> Self {
+        Self {
+            initial_names: names,
+            names,
+            active,
+            index: active,
+            cursor: None,
+        }
+    }
+
+    fn handle_input(&mut self, e: &InputEvent, cx: &mut ViewContext) {
+        match (self.cursor, e) {
+            (None, InputEvent::ButtonA(ButtonEvent::Press)) => {
+                self.index = (self.index + 1) % PROFILE_COUNT;
+            }
+            (None, InputEvent::ButtonB(ButtonEvent::Press)) => {
+                self.index = (self.index + PROFILE_COUNT - 1) % PROFILE_COUNT;
+            }
+            (None, InputEvent::ButtonA(ButtonEvent::LongPress)) => self.cursor = Some(0),
+            (None, InputEvent::ButtonB(ButtonEvent::LongPress)) => {
+                if self.index != self.active {
+                    cx.send(AppMessage::SelectProfile(self.index));
+                }
+                cx.pop_view();
+            }
+            (Some(cursor), InputEvent::ButtonA(ButtonEvent::Press)) => {
+                self.names[self.index].change(cursor, true)
+            }
+            (Some(cursor), InputEvent::ButtonB(ButtonEvent::Press)) => {
+                self.names[self.index].change(cursor, false)
+            }
+            (Some(cursor), InputEvent::ButtonA(ButtonEvent::LongPress)) => {
+                self.cursor = Some((cursor + 1) % NAME_SIZE);
+            }
+            (Some(_), InputEvent::ButtonB(ButtonEvent::LongPress)) => {
+                let name = self.names[self.index];
+                if name != self.initial_names[self.index] {
+                    cx.send(AppMessage::RenameProfile(self.index, name));
+                    self.initial_names[self.index] = name;
+                }
+                self.cursor = None;
+            }
+        }
+    }
+}
+
+impl View for ProfileMenu {
+    fn handle_message(&mut self, m: &AppMessage, cx: &mut ViewContext) -> MessageProcessingStatus {
+        if let AppMessage::InputEvent(e) = m {
+            self.handle_input(e, cx);
+            MessageProcessingStatus::Processed
+        } else {
+            MessageProcessingStatus::Ignored
+        }
+    }
+
+    fn render(&mut self, terminal: &mut dyn Terminal, _: &AppState) -> core::fmt::Result {
+        terminal.set_position(0, 0)?;
+        terminal.write_str("PROFILES\n\n")?;
+        for (i, name) in self.names.iter().enumerate() {
+            let marker = match (i == self.index, self.cursor) {
+                (true, None) => '>',
+                (true, Some(_)) => '*',
+                (false, _) => ' ',
+            };
+            terminal.write_fmt(format_args!(
+                "{marker}{:<8} {:<6}\n",
+                name.as_str(),
+                if i == self.active { "ACTIVE" } else { "" }
+            ))?;
+        }
+        // The caret under the character being changed, padded to clear the line
+        let (caret, width) = match self.cursor {
+            Some(cursor) => ("^", cursor + 2),
+            None => ("", 0),
+        };
+        terminal.write_fmt(format_args!("{caret:>width$}{:1$}\n\n", "", 16 - width))?;
+        terminal.write_str(if self.cursor.is_some() {
+            "HOLD B: DONE   "
+        } else {
+            "HOLD B: SELECT "
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+
+    use super::*;
+    use crate::{
+        common::AppContext,
+        testing::{input, show, take_messages},
+    };
+    use stuff::run_loop::{Task, TaskStatus};
+
+    fn names() -> [Name; PROFILE_COUNT] {
+        core::array::from_fn(numbered_name)
+    }
+
+    #[test]
+    fn names_are_numbered() {
+        assert_eq!(numbered_name(1).as_str(), "PROFILE2");
+    }
+
+    #[test]
+    fn profile_is_selected() {
+        let mut cx = AppContext::default();
+        let (mut stack, terminal) = show(Box::new(ProfileMenu::new(names(), 1)));
+
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(0), "PROFILES");
+        assert_eq!(terminal.borrow().line(2), " PROFILE1");
+        assert_eq!(terminal.borrow().line(3), ">PROFILE2 ACTIVE");
+
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::Press));
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::Press));
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(2), ">PROFILE1");
+
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::LongPress));
+        assert!(matches!(stack.run(&mut cx), TaskStatus::Done));
+        assert_eq!(take_messages(&mut cx), [AppMessage::SelectProfile(0)]);
+    }
+
+    #[test]
+    fn active_profile_is_not_selected_again() {
+        let mut cx = AppContext::default();
+        let (mut stack, _) = show(Box::new(ProfileMenu::new(names(), 2)));
+
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::LongPress));
+        assert!(matches!(stack.run(&mut cx), TaskStatus::Done));
+        assert_eq!(take_messages(&mut cx), []);
+    }
+
+    #[test]
+    fn profile_is_renamed() {
+        let mut cx = AppContext::default();
+        let (mut stack, terminal) = show(Box::new(ProfileMenu::new(names(), 0)));
+
+        // Change the first two characters: 'P' to 'O' and 'R' to 'S'
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::LongPress));
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::Press));
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::LongPress));
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::Press));
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(2), "*OSOFILE1 ACTIVE");
+        assert_eq!(terminal.borrow().line(5), "  ^");
+        assert_eq!(terminal.borrow().line(7), "HOLD B: DONE");
+
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::LongPress));
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(2), ">OSOFILE1 ACTIVE");
+        assert_eq!(terminal.borrow().line(5), "");
+        let name = Name::from_bytes(*b"OSOFILE1").unwrap();
+        assert_eq!(take_messages(&mut cx), [AppMessage::RenameProfile(0, name)]);
+    }
+}
@@ -0,0 +1,119 @@
+use libm::{fabsf, roundf};
+
+/// The shown value changes only once the weight is this many divisions past
+/// the midpoint between two steps.
+const HYSTERESIS: f32 = 0.25;
+
+/// Closer to zero than this many divisions, the weight is shown as zero.
+const ZERO_BAND: f32 = 1.0;
+
+/// Rounds the readouts to the display division without flicker.
+///
+/// The shown value follows the weight only when it's clearly closer to
+/// another step, i.e. a noisy weight around the midpoint between two steps
+/// doesn't make the last digit alternate. A weight near zero is shown as an
+/// exact zero, never as a negative one.
+pub struct Quantizer {
+    division: f32,
+    value: f32,
+}
+
+impl Quantizer {
+    /// `division` is the step of the shown values, in their unit.
+    pub fn new(division: f32) -> Self {
+        assert!(division > 0.0);
+        Self {
+            division,
+            value: 0.0,
+        }
+    }
+
+    pub fn division(&self) -> f32 {
+        self.division
+    }
+
+    /// The shown value, a multiple of the division.
+    pub fn value(&self) -> f32 {
+        self.value
+    }
+
+    /// Feed a readout. Returns the shown value.
+    pub fn update(&mut self, weight: f32) -> f32 {
+        if fabsf(weight) < self.division * ZERO_BAND {
+            self.value = 0.0;
+        } else if fabsf(weight - self.value) > self.division * (0.5 + HYSTERESIS) {
+            self.value = roundf(weight / self.division) * self.division;
+        }
+        self.value
+    }
+}
+
+/// The number of decimals of the multiples of the division, up to three.
+pub fn decimals(division: f32) -> usize {
+    if division >= 1.0 {
+        0
+    } else if division >= 0.1 {
+        1
+    } else if division >= 0.01 {
+        2
+    } else {
+        3
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_shown(q: &mut Quantizer, weight: f32, expected: f32) {
+        let value = q.update(weight);
+        assert!(
+            fabsf(value - expected) < 1e-4,
+            "{weight} is shown as {value}, not {expected}"
+        );
+    }
+
+    #[test]
+    fn weight_is_rounded_to_the_division() {
+        let mut q = Quantizer::new(0.5);
+        assert_shown(&mut q, 12.3, 12.5);
+        assert_shown(&mut q, 100.0, 100.0);
+        assert_shown(&mut q, -7.2, -7.0);
+
+        let mut q = Quantizer::new(0.05);
+        assert_shown(&mut q, 2.97, 2.95);
+    }
+
+    #[test]
+    fn noise_around_a_midpoint_does_not_flicker() {
+        let mut q = Quantizer::new(0.1);
+        assert_shown(&mut q, 12.44, 12.4);
+        assert_shown(&mut q, 12.46, 12.4);
+        assert_shown(&mut q, 12.44, 12.4);
+        assert_shown(&mut q, 12.47, 12.4);
+        // Clearly closer to the next step
+        assert_shown(&mut q, 12.48, 12.5);
+        assert_shown(&mut q, 12.44, 12.5);
+        assert_shown(&mut q, 12.42, 12.4);
+    }
+
+    #[test]
+    fn weight_near_zero_is_blanked() {
+        let mut q = Quantizer::new(0.1);
+        assert_shown(&mut q, 0.09, 0.0);
+        assert_shown(&mut q, -0.06, 0.0);
+        assert!(q.value().is_sign_positive());
+        assert_shown(&mut q, 0.12, 0.1);
+        assert_shown(&mut q, -0.12, -0.1);
+    }
+
+    #[test]
+    fn decimals_follow_the_division() {
+        assert_eq!(decimals(0.001), 3);
+        assert_eq!(decimals(0.01), 2);
+        assert_eq!(decimals(0.05), 2);
+        assert_eq!(decimals(0.1), 1);
+        assert_eq!(decimals(0.5), 1);
+        assert_eq!(decimals(1.0), 0);
+    }
+}
@@ -0,0 +1,441 @@
+use alloc::{format, string::String};
+
+use crate::{
+    button::ButtonEvent,
+    common::{AppMessage, AppState, Duration, InputEvent, Instant},
+    dashboard::{handle_long_press, switch_mode},
+    name::Name,
+    settings::Mode,
+    terminal::Terminal,
+    units::weight_row,
+    view_stack::{View, ViewContext},
+};
+use stuff::mq::MessageProcessingStatus;
+
+pub const RECIPE_COUNT: usize = 2;
+pub const RECIPE_CAPACITY: usize = 8;
+
+/// A step is done once the weight is this close to its target, in grams.
+const WEIGHT_TOLERANCE: f32 = 0.5;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StepKind {
+    Bloom,
+    Pour,
+    Wait,
+}
+
+impl StepKind {
+    fn label(&self) -> &'static str {
+        match self {
+            StepKind::Bloom => "BLOOM",
+            StepKind::Pour => "POUR",
+            StepKind::Wait => "WAIT",
+        }
+    }
+}
+
+/// A step of a recipe, done when all of its targets are met.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Step {
+    pub kind: StepKind,
+    /// The weight to reach, in grams after the tare.
+    pub weight: Option<f32>,
+    /// The brew time to reach, in seconds since the start of the recipe.
+    pub time: Option<u16>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Error {
+    Full,
+    /// A step needs a weight or a time to be done.
+    NoTarget,
+}
+
+/// A named sequence of steps.
+///
+/// There is no way to edit the recipes on the scale yet, so they are always
+/// the [`Recipe::defaults`] and aren't stored in the configuration.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Recipe {
+    pub name: Name,
+    steps: [Step; RECIPE_CAPACITY],
+    len: usize,
+}
+
+impl Default for Recipe {
+    fn default() -> Self {
+        Self::new(Name::default())
+    }
+}
+
+impl Recipe {
+    pub fn new(name: Name) -> Self {
+        Self {
+            name,
+            steps: [Step {
+                kind: StepKind::Wait,
+                weight: None,
+                time: None,
+            }; RECIPE_CAPACITY],
+            len: 0,
+        }
+    }
+
+    pub fn steps(&self) -> &[Step] {
+        &self.steps[..self.len]
+    }
+
+    pub fn push(&mut self, step: Step) -> Result<(), Error> {
+        if step.weight.is_none() && step.time.is_none() {
+            return Err(Error::NoTarget);
+        }
+        let slot = self.steps.get_mut(self.len).ok_or(Error::Full)?;
+        *slot = step;
+        self.len += 1;
+        Ok(())
+    }
+
+    fn with_steps(name: &[u8; 8], steps: &[(StepKind, Option<f32>, Option<u16>)]) -> Self {
+        let mut recipe = Self::new(Name::from_bytes(*name).unwrap());
+        for &(kind, weight, time) in steps {
+            recipe.push(Step { kind, weight, time }).unwrap();
+        }
+        recipe
+    }
+
+    /// The recipes the scale comes with.
+    pub fn defaults() -> [Recipe; RECIPE_COUNT] {
+        use StepKind::*;
+        [
+            Self::with_steps(
+                b"V60     ",
+                &[
+                    (Bloom, Some(50.0), Some(45)),
+                    (Pour, Some(150.0), Some(75)),
+                    (Pour, Some(250.0), Some(90)),
+                    (Wait, None, Some(180)),
+                ],
+            ),
+            Self::with_steps(
+                b"CHEMEX  ",
+                &[
+                    (Bloom, Some(60.0), Some(45)),
+                    (Pour, Some(300.0), Some(105)),
+                    (Pour, Some(500.0), Some(165)),
+                    (Wait, None, Some(270)),
+                ],
+            ),
+        ]
+    }
+}
+
+/// Follows the steps of a recipe from the readouts.
+pub struct RecipeRunner {
+    recipe: Recipe,
+    start: Instant,
+    end: Option<Instant>,
+    index: usize,
+}
+
+impl RecipeRunner {
+    pub fn new(recipe: Recipe, start: Instant) -> Self {
+        Self {
+            recipe,
+            start,
+            end: None,
+            index: 0,
+        }
+    }
+
+    pub fn recipe(&self) -> &Recipe {
+        &self.recipe
+    }
+
+    /// The index of the current step, the number of steps once done.
+    pub fn step_index(&self) -> usize {
+        self.index
+    }
+
+    pub fn step(&self) -> Option<&Step> {
+        self.recipe.steps().get(self.index)
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.step().is_none()
+    }
+
+    /// The brew time, stopped once the recipe is done.
+    pub fn elapsed(&self, now: Instant) -> Duration {
+        self.end.unwrap_or(now) - self.start
+    }
+
+    /// The weight left to the target of the current step, in grams.
+    pub fn remaining_weight(&self, weight: f32) -> Option<f32> {
+        let target = self.step()?.weight?;
+        Some((target - weight).max(0.0))
+    }
+
+    /// The time left to the target of the current step.
+    pub fn remaining_time(&self, now: Instant) -> Option<Duration> {
+        let target = Duration::secs(self.step()?.time? as u64);
+        Some(
+            target
+                .checked_sub(self.elapsed(now))
+                .unwrap_or(Duration::from_ticks(0)),
+        )
+    }
+
+    /// Move past the steps whose targets are met. Returns whether the step
+    /// has changed.
+    pub fn update(&mut self, now: Instant, weight: f32) -> bool {
+        let index = self.index;
+        let elapsed = self.elapsed(now);
+        while self.step().is_some_and(|step| {
+            step.weight.is_none_or(|w| weight >= w - WEIGHT_TOLERANCE)
+                && step
+                    .time
+                    .is_none_or(|t| elapsed >= Duration::secs(t as u64))
+        }) {
+            self.index += 1;
+        }
+        if self.is_done() && self.end.is_none() {
+            self.end = Some(now);
+        }
+        self.index != index
+    }
+}
+
+/// The main screen of [`Mode::Recipe`].
+///
+/// Button A tares, button B starts the recipe selected in the settings
+/// and stops it. The long presses are the same as on the dashboard.
+pub struct RecipeView {
+    get_instant: fn() -> Instant,
+    runner: Option<RecipeRunner>,
+    /// Whether the rows that change with the step are rendered.
+    is_step_rendered: bool,
+}
+
+impl RecipeView {
+    pub fn new(get_instant: fn() -> Instant) -> Self {
+        Self {
+            get_instant,
+            runner: None,
+            is_step_rendered: false,
+        }
+    }
+
+    fn format_time(d: Duration) -> String {
+        format!("{}:{:02}", d.to_minutes(), d.to_secs() % 60)
+    }
+}
+
+impl View for RecipeView {
+    fn handle_message(&mut self, m: &AppMessage, cx: &mut ViewContext) -> MessageProcessingStatus {
+        if let AppMessage::InputEvent(e) = m {
+            match e {
+                InputEvent::ButtonA(ButtonEvent::Press) => cx.send(AppMessage::Tare),
+                InputEvent::ButtonB(ButtonEvent::Press) => {
+                    self.runner = match self.runner {
+                        Some(_) => None,
+                        None => {
+                            let recipe = cx.state.recipes[cx.state.settings.recipe as usize];
+                            Some(RecipeRunner::new(recipe, (self.get_instant)()))
+                        }
+                    };
+                    self.is_step_rendered = false;
+                }
+                e => handle_long_press(e, cx),
+            }
+            MessageProcessingStatus::Processed
+        } else {
+            MessageProcessingStatus::Ignored
+        }
+    }
+
+    fn render(&mut self, terminal: &mut dyn Terminal, state: &AppState) -> core::fmt::Result {
+        if !self.is_step_rendered {
+            terminal.clear()?;
+            self.is_step_rendered = true;
+        }
+        terminal.set_position(0, 0)?;
+        let Some(runner) = &self.runner else {
+            let recipe = &state.recipes[state.settings.recipe as usize];
+            terminal.write_fmt(format_args!(
+                "{:<8}{:>8}\n\n\n\n\n\n\nB: START",
+                recipe.name.as_str(),
+                format!("{} STEPS", recipe.steps().len()),
+            ))?;
+            return Ok(());
+        };
+        let now = (self.get_instant)();
+        let recipe = runner.recipe();
+        let step = runner.step();
+        terminal.write_fmt(format_args!(
+            "{:<8}{:>8}\n{}\n\n",
+            recipe.name.as_str(),
+            format!(
+                "{}/{}",
+                (runner.step_index() + 1).min(recipe.steps().len()),
+                recipe.steps().len()
+            ),
+            step.map_or("DONE", |s| s.kind.label()),
+        ))?;
+        let unit = state.settings.unit;
+        if let Some(target) = step.and_then(|s| s.weight) {
+            let left = runner.remaining_weight(state.weight).unwrap_or(0.0);
+            let division = unit.division(state.settings.division());
+            terminal.write_fmt(format_args!(
+                "{}\n{}\n",
+                weight_row("TARGET", target, unit, division),
+                weight_row("LEFT", left, unit, division),
+            ))?;
+        } else {
+            terminal.write_str("\n\n")?;
+        }
+        if let Some(left) = runner.remaining_time(now) {
+            terminal.write_fmt(format_args!("TIME LEFT{:>7}", Self::format_time(left)))?;
+        }
+        terminal.set_position(0, 6)?;
+        terminal.write_fmt(format_args!(
+            "TOTAL{:>11}",
+            Self::format_time(runner.elapsed(now))
+        ))
+    }
+
+    fn update(&mut self, cx: &mut ViewContext) {
+        if switch_mode(Mode::Recipe, self.get_instant, cx) {
+            return;
+        }
+        if let Some(runner) = self.runner.as_mut()
+            && runner.update((self.get_instant)(), cx.state.weight)
+        {
+            self.is_step_rendered = false;
+        }
+    }
+
+    fn appear(&mut self) {
+        self.is_step_rendered = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::{AtomicU64, Ordering};
+
+    use super::*;
+    use crate::{
+        common::AppContext,
+        dashboard::home_view,
+        testing::{input, show},
+    };
+    use stuff::run_loop::Task;
+
+    fn seconds(s: u64) -> Instant {
+        Instant::from_ticks(s * 1_000_000)
+    }
+
+    #[test]
+    fn step_needs_a_target() {
+        let mut recipe = Recipe::default();
+        let step = Step {
+            kind: StepKind::Pour,
+            weight: None,
+            time: None,
+        };
+        assert_eq!(recipe.push(step), Err(Error::NoTarget));
+        let step = Step {
+            time: Some(10),
+            ..step
+        };
+        for _ in 0..RECIPE_CAPACITY {
+            recipe.push(step).unwrap();
+        }
+        assert_eq!(recipe.push(step), Err(Error::Full));
+        assert_eq!(recipe.steps().len(), RECIPE_CAPACITY);
+    }
+
+    #[test]
+    fn steps_advance_once_all_targets_are_met() {
+        let [v60, _] = Recipe::defaults();
+        let mut runner = RecipeRunner::new(v60, seconds(0));
+        assert_eq!(runner.remaining_weight(20.0), Some(30.0));
+        assert_eq!(runner.remaining_time(seconds(15)), Some(Duration::secs(30)));
+        // The weight is reached before the time
+        assert!(!runner.update(seconds(20), 49.6));
+        assert!(runner.update(seconds(45), 49.6));
+        assert_eq!(runner.step().unwrap().kind, StepKind::Pour);
+        // Both pours are done at once
+        assert!(runner.update(seconds(90), 250.0));
+        assert_eq!(runner.step_index(), 3);
+        assert_eq!(runner.remaining_weight(250.0), None);
+        assert!(runner.update(seconds(180), 250.0));
+        assert!(runner.is_done());
+        assert_eq!(runner.elapsed(seconds(200)), Duration::secs(180));
+        assert!(!runner.update(seconds(200), 250.0));
+    }
+
+    #[test]
+    fn recipe_is_followed() {
+        static NOW: AtomicU64 = AtomicU64::new(0);
+        let set_seconds = |s: u64| NOW.store(s * 1_000_000, Ordering::Relaxed);
+        let mut cx = AppContext::default();
+        cx.state.settings.mode = Mode::Recipe;
+        cx.state.recipes = Recipe::defaults();
+        let (mut stack, terminal) = show(Box::new(RecipeView::new(|| {
+            Instant::from_ticks(NOW.load(Ordering::Relaxed))
+        })));
+
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(0), "V60      4 STEPS");
+        assert_eq!(terminal.borrow().line(7), "B: START");
+
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::Press));
+        stack.run(&mut cx);
+        set_seconds(10);
+        cx.state.weight = 20.0;
+        stack.run(&mut cx);
+        let lines: [String; 7] = core::array::from_fn(|i| terminal.borrow().line(i));
+        assert_eq!(
+            lines,
+            [
+                "V60          1/4",
+                "BLOOM",
+                "",
+                "TARGET    50.0 g",
+                "LEFT      30.0 g",
+                "TIME LEFT   0:35",
+                "TOTAL       0:10",
+            ]
+        );
+
+        set_seconds(180);
+        cx.state.weight = 250.0;
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(0), "V60          4/4");
+        assert_eq!(terminal.borrow().line(1), "DONE");
+        assert_eq!(terminal.borrow().line(3), "");
+        assert_eq!(terminal.borrow().line(5), "");
+        assert_eq!(terminal.borrow().line(6), "TOTAL       3:00");
+
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::Press));
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(1), "");
+        assert_eq!(terminal.borrow().line(7), "B: START");
+    }
+
+    #[test]
+    fn mode_is_switched_from_the_settings() {
+        let mut cx = AppContext::default();
+        let (mut stack, terminal) = show(home_view(Mode::Recipe, || Instant::from_ticks(0)));
+
+        cx.state.settings.mode = Mode::Recipe;
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(7), "B: START");
+        cx.state.settings.mode = Mode::Espresso;
+        stack.run(&mut cx);
+        assert_eq!(stack.len(), 1);
+        assert_eq!(terminal.borrow().line(0), "ESPRESSO");
+    }
+}
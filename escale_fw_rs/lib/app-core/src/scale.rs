@@ -1,35 +1,96 @@
 use num_traits::{float::FloatCore, PrimInt};
-use stuff::{simple_ring::SimpleRing, signal::mean};
+use stuff::{
+    signal::{Mean, Passthrough, SampleFilter, WindowFilter},
+    simple_ring::SimpleRing,
+};
 
-pub struct Scale<T: PrimInt, U: FloatCore, const N: usize>
+use crate::calibration_table::{self, CalibrationPoint, CalibrationTable};
+
+/// `P` is the filter applied to every new sample before it's buffered
+/// (e.g. spike rejection), `F` is the filter applied to the buffer
+/// to produce a readout.
+pub struct Scale<T: PrimInt, U: FloatCore, const N: usize, F = Mean, P = Passthrough>
 where
     // Require N ≥ 1
     [(); N - 1]:,
 {
     ring: SimpleRing<T, N>,
+    filter: F,
+    prefilter: P,
     tare: U,
+    /// The tare as captured, the reference for zero tracking limits.
+    captured_tare: U,
     unit: U,
+    /// Takes precedence over `unit` when it has enough points.
+    calibration: CalibrationTable<U>,
+    stability: Stability<U>,
+    zero_tracking: Option<ZeroTracking<U>>,
+}
+
+/// The readout is considered stable when the spread (max − min) of the last
+/// `window` buffered samples, in output units, does not exceed `threshold`.
+#[derive(Clone, Copy, Debug)]
+pub struct Stability<U> {
+    pub window: usize,
+    pub threshold: U,
+}
+
+/// Automatic zero tracking compensates slow drift of the zero point.
+///
+/// While the readout is stable and within `band` around zero, the tare
+/// follows the readout by `rate` (a fraction of the offset per sample),
+/// but never wanders further than `limit` from the captured tare.
+/// `band` and `limit` are in output units.
+#[derive(Clone, Copy, Debug)]
+pub struct ZeroTracking<U> {
+    pub band: U,
+    pub rate: U,
+    pub limit: U,
 }
 
-impl<T: PrimInt + Default, U: FloatCore, const N: usize> Default for Scale<T, U, N>
+impl<T: PrimInt + Default, U: FloatCore, const N: usize, F: Default, P: Default> Default
+    for Scale<T, U, N, F, P>
 where
     [(); N - 1]:,
 {
     fn default() -> Self {
+        Self::new(F::default(), P::default())
+    }
+}
+
+impl<T: PrimInt + Default, U: FloatCore, const N: usize, F, P> Scale<T, U, N, F, P>
+where
+    [(); N - 1]:,
+{
+    pub fn new(filter: F, prefilter: P) -> Self {
         Self {
             ring: Default::default(),
+            filter,
+            prefilter,
             unit: U::one(),
             tare: U::zero(),
+            captured_tare: U::zero(),
+            calibration: Default::default(),
+            stability: Stability {
+                window: N,
+                threshold: U::infinity(),
+            },
+            zero_tracking: None,
         }
     }
 }
 
-impl<T: PrimInt, U: FloatCore, const N: usize> Scale<T, U, N>
+impl<T, U, const N: usize, F, P> Scale<T, U, N, F, P>
 where
+    T: PrimInt,
+    U: FloatCore,
+    F: WindowFilter<T, U>,
+    P: SampleFilter<T>,
     [(); N - 1]:,
 {
     pub fn push(&mut self, value: T) {
-        self.ring.push(value);
+        self.ring.push(self.prefilter.apply(value));
+        self.track_zero();
     }
 
     pub fn is_filled(&self) -> bool {
@@ -37,37 +98,134 @@ where
     }
 
     pub fn reset(&mut self) {
-        self.ring.reset(T::zero())
+        self.ring.reset(T::zero());
+        self.prefilter.reset();
     }
 
-    /// Set the zero offset (tare) based on the current buffer.
+    /// Replace the filter. The buffered samples are kept.
+    pub fn set_filter(&mut self, filter: F) {
+        self.filter = filter;
+    }
+
+    pub fn set_stability(&mut self, stability: Stability<U>) {
+        assert!(stability.window >= 2 && stability.window <= N);
+        self.stability = stability;
+    }
+
+    pub fn get_stability(&self) -> Stability<U> {
+        self.stability
+    }
+
+    /// Check whether the readout is stable (see [`Stability`]).
     ///
     /// The buffer must be filled.
-    pub fn capture_tare(&mut self) -> Result<(), Error> {
+    pub fn is_stable(&self) -> Result<bool, Error> {
         if self.is_filled() {
-            self.tare = self.read_raw()?;
-            Ok(())
+            Ok(self.spread() <= self.stability.threshold)
         } else {
             Err(Error::NotFilled)
         }
     }
 
+    fn spread(&self) -> U {
+        let (min, max) = self
+            .ring
+            .iter()
+            .rev()
+            .take(self.stability.window)
+            .fold((T::max_value(), T::min_value()), |(min, max), &x| {
+                (min.min(x), max.max(x))
+            });
+        (self.adjust(Self::convert(max)) - self.adjust(Self::convert(min))).abs()
+    }
+
+    pub fn set_zero_tracking(&mut self, zero_tracking: Option<ZeroTracking<U>>) {
+        if let Some(zt) = zero_tracking {
+            assert!(zt.rate > U::zero() && zt.rate <= U::one());
+            assert!(zt.band >= U::zero() && zt.limit >= U::zero());
+        }
+        self.zero_tracking = zero_tracking;
+    }
+
+    pub fn get_zero_tracking(&self) -> Option<ZeroTracking<U>> {
+        self.zero_tracking
+    }
+
+    fn track_zero(&mut self) {
+        if let Some(zt) = self.zero_tracking && self.is_stable() == Ok(true) {
+            let raw = self.read_raw().unwrap();
+            if self.adjust(raw).abs() <= zt.band {
+                let limit = zt.limit * self.effective_unit().abs();
+                let tare = self.tare + (raw - self.tare) * zt.rate;
+                self.tare = tare
+                    .max(self.captured_tare - limit)
+                    .min(self.captured_tare + limit);
+            }
+        }
+    }
+
+    /// Fails with [`Error::Unstable`] unless the readout is stable.
+    pub fn ensure_stable(&self) -> Result<(), Error> {
+        if self.is_stable()? {
+            Ok(())
+        } else {
+            Err(Error::Unstable)
+        }
+    }
+
+    /// Set the zero offset (tare) based on the current buffer.
+    ///
+    /// The buffer must be filled and the readout must be stable.
+    pub fn capture_tare(&mut self) -> Result<(), Error> {
+        self.ensure_stable()?;
+        self.tare = self.read_raw()?;
+        self.captured_tare = self.tare;
+        Ok(())
+    }
+
+    pub fn set_tare(&mut self, tare: U) {
+        self.tare = tare;
+        self.captured_tare = tare;
+    }
+
+    /// The raw readout corresponding to zero.
+    pub fn get_tare(&self) -> U {
+        self.tare
+    }
+
     /// Set the calibration coefficient based on the current buffer.
     ///
     /// `value` allows to set the unit to a fraction of the current readout.
     ///
     /// If the desired unit is 1 g and a 100 g weight is used for calibration,
     /// set `value` to 100.
+    ///
+    /// The buffer must be filled and the readout must be stable.
+    ///
+    /// Starts the multi-point calibration over with the zero point and this
+    /// one, so that more points can be added with [`Self::capture_point`].
     pub fn capture_unit(&mut self, value: U) -> Result<(), Error> {
-        assert!(value != U::zero());
-        if self.is_filled() {
-            let unit = (self.read_raw()? - self.tare) / value;
-            assert!(unit != U::zero());
-            self.unit = unit;
-            Ok(())
-        } else {
-            Err(Error::NotFilled)
+        if value == U::zero() {
+            return Err(Error::ZeroUnit);
+        }
+        self.ensure_stable()?;
+        let raw = self.read_raw()? - self.tare;
+        let unit = raw / value;
+        if unit == U::zero() {
+            return Err(Error::ZeroUnit);
         }
+        self.unit = unit;
+        self.calibration.clear();
+        [
+            CalibrationPoint {
+                raw: U::zero(),
+                value: U::zero(),
+            },
+            CalibrationPoint { raw, value },
+        ]
+        .into_iter()
+        .try_for_each(|p| self.calibration.insert(p))
+        .map_err(Error::Calibration)
     }
 
     pub fn set_unit(&mut self, unit: U) {
@@ -79,29 +237,293 @@ where
         self.unit
     }
 
+    /// Add a multi-point calibration reference point based on the current
+    /// buffer. The point replaces an existing one with the same `value`.
+    ///
+    /// The buffer must be filled and the readout must be stable.
+    pub fn capture_point(&mut self, value: U) -> Result<(), Error> {
+        self.ensure_stable()?;
+        let raw = self.read_raw()? - self.tare;
+        self.calibration
+            .insert(CalibrationPoint { raw, value })
+            .map_err(Error::Calibration)
+    }
+
+    pub fn set_calibration(&mut self, calibration: CalibrationTable<U>) {
+        assert!(calibration.is_valid());
+        self.calibration = calibration;
+    }
+
+    pub fn get_calibration(&self) -> &CalibrationTable<U> {
+        &self.calibration
+    }
+
+    /// The output units per raw unit, the finest step of the readout.
+    pub fn resolution(&self) -> U {
+        U::one() / self.effective_unit().abs()
+    }
+
+    /// The number of raw units per output unit.
+    fn effective_unit(&self) -> U {
+        self.calibration.unit().unwrap_or(self.unit)
+    }
+
     fn read_raw(&self) -> Result<U, Error> {
+        const E_FILTER_MUST_APPLY: &str = "The filter must accept a filled buffer";
         if self.is_filled() {
-            Ok(mean(self.ring.iter().copied()).unwrap())
+            Ok(self
+                .filter
+                .apply(self.ring.iter().copied())
+                .expect(E_FILTER_MUST_APPLY))
         } else {
             Err(Error::NotFilled)
         }
     }
 
     pub fn read(&self) -> Result<U, Error> {
-        if self.is_filled() {
-            Ok(mean(self.ring.iter().copied().map(|x| self.adjust(x))).unwrap())
-        } else {
-            Err(Error::NotFilled)
-        }
+        self.read_raw().map(|raw| self.adjust(raw))
     }
 
-    fn adjust(&self, raw: T) -> U {
+    fn adjust(&self, raw: U) -> U {
+        let net = raw - self.tare;
+        self.calibration.map(net).unwrap_or_else(|| net / self.unit)
+    }
+
+    fn convert(raw: T) -> U {
         const E_RAW_MUST_FIT: &str = "Raw readout must fit into the output floating point type";
-        (U::from(raw).expect(E_RAW_MUST_FIT) - self.tare) / self.unit
+        U::from(raw).expect(E_RAW_MUST_FIT)
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 pub enum Error {
     NotFilled,
+    /// The readout is not stable enough to be captured.
+    Unstable,
+    /// The reference is zero or reads as the tare, so there is no unit.
+    ZeroUnit,
+    Calibration(calibration_table::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use stuff::signal::MedianFilter;
+
+    use crate::{
+        scale_fir::{scale_fir, ScaleFir, SCALE_FIR_TAP_COUNT},
+        settings::FilterStrength,
+    };
+
+    #[test]
+    fn mean_scale_reads_after_filled() {
+        let mut scale = Scale::<i32, f32, 4>::default();
+        scale.push(10);
+        assert!(scale.read().is_err());
+        scale.push(20);
+        scale.push(30);
+        scale.push(40);
+        assert_eq!(scale.read().unwrap(), 25.0);
+    }
+
+    #[test]
+    fn fir_scale_tares_and_calibrates() {
+        let mut scale = Scale::<i32, f32, SCALE_FIR_TAP_COUNT, ScaleFir>::new(
+            scale_fir(FilterStrength::Medium),
+            Passthrough,
+        );
+        for _ in 0..SCALE_FIR_TAP_COUNT {
+            scale.push(1_000);
+        }
+        scale.capture_tare().unwrap();
+        assert_eq!(scale.read().unwrap(), 0.0);
+
+        for _ in 0..SCALE_FIR_TAP_COUNT {
+            scale.push(51_000);
+        }
+        scale.capture_unit(100.0).unwrap();
+        assert_eq!(scale.get_unit(), 500.0);
+        assert_eq!(scale.read().unwrap(), 100.0);
+        assert_eq!(scale.resolution(), 0.002);
+    }
+
+    #[test]
+    fn prefilter_keeps_spikes_out_of_the_readout() {
+        let mut scale = Scale::<i32, f32, 4, Mean, MedianFilter<i32, 3>>::default();
+        for x in [100, 100, 100, 100] {
+            scale.push(x);
+        }
+        scale.capture_tare().unwrap();
+        for x in [101, 99, 90_000, 100, 101, -90_000, 99] {
+            scale.push(x);
+            assert!(scale.read().unwrap().abs() <= 1.0);
+        }
+    }
+
+    #[test]
+    fn stability_is_detected_over_the_window() {
+        let mut scale = Scale::<i32, f32, 8>::default();
+        scale.set_stability(Stability {
+            window: 4,
+            threshold: 2.0,
+        });
+        assert_eq!(scale.is_stable(), Err(Error::NotFilled));
+        for x in [0, 50, 100, 150, 200, 201, 199, 200] {
+            scale.push(x);
+        }
+        assert_eq!(scale.is_stable(), Ok(true));
+        scale.push(210);
+        assert_eq!(scale.is_stable(), Ok(false));
+
+        // The threshold is in output units.
+        scale.set_unit(10.0);
+        assert_eq!(scale.is_stable(), Ok(true));
+    }
+
+    #[test]
+    fn capture_requires_stability() {
+        let mut scale = Scale::<i32, f32, 4>::default();
+        scale.set_stability(Stability {
+            window: 4,
+            threshold: 2.0,
+        });
+        for x in [0, 10, 0, 10] {
+            scale.push(x);
+        }
+        assert_eq!(scale.capture_tare(), Err(Error::Unstable));
+        assert_eq!(scale.capture_unit(100.0), Err(Error::Unstable));
+
+        for x in [5, 5, 6, 5] {
+            scale.push(x);
+        }
+        assert_eq!(scale.capture_tare(), Ok(()));
+        assert_eq!(scale.read(), Ok(0.0));
+    }
+
+    #[test]
+    fn unit_is_not_captured_without_a_reference() {
+        let mut scale = Scale::<i32, f32, 4>::default();
+        for _ in 0..4 {
+            scale.push(1_000);
+        }
+        scale.capture_tare().unwrap();
+        assert_eq!(scale.capture_unit(0.0), Err(Error::ZeroUnit));
+        // Nothing on the scale
+        assert_eq!(scale.capture_unit(100.0), Err(Error::ZeroUnit));
+        assert_eq!(scale.get_unit(), 1.0);
+    }
+
+    /// An empty scale drifting by `drift` raw units per sample
+    /// with a ±1 raw unit noise.
+    fn drifting(i: i32, drift: f32) -> i32 {
+        1_000 + (i as f32 * drift) as i32 + (i % 3 - 1)
+    }
+
+    fn tracking_scale() -> Scale<i32, f32, 8> {
+        let mut scale = Scale::<i32, f32, 8>::default();
+        scale.set_unit(100.0);
+        scale.set_stability(Stability {
+            window: 8,
+            threshold: 0.05,
+        });
+        for i in 0..8 {
+            scale.push(drifting(i, 0.0));
+        }
+        scale.capture_tare().unwrap();
+        scale.set_zero_tracking(Some(ZeroTracking {
+            band: 0.2,
+            rate: 0.05,
+            limit: 1.0,
+        }));
+        scale
+    }
+
+    #[test]
+    fn zero_tracking_compensates_drift() {
+        let mut scale = tracking_scale();
+        for i in 8..2_000 {
+            scale.push(drifting(i, 0.02));
+            assert!(scale.read().unwrap().abs() < 0.05);
+        }
+
+        scale.set_zero_tracking(None);
+        for i in 2_000..4_000 {
+            scale.push(drifting(i, 0.02));
+        }
+        assert!(scale.read().unwrap() > 0.3);
+    }
+
+    #[test]
+    fn zero_tracking_ignores_load() {
+        let mut scale = tracking_scale();
+        for i in 8..500 {
+            scale.push(drifting(i, 0.0) + 1_000);
+        }
+        assert_eq!(scale.read().unwrap(), 10.0);
+    }
+
+    #[test]
+    fn zero_tracking_is_limited() {
+        let mut scale = tracking_scale();
+        for i in 8..20_000 {
+            scale.push(drifting(i, 0.02));
+        }
+        // 400 raw units of drift, but only 100 (1.0 × unit) are compensated:
+        // the readout is (1398.875 − (999.875 + 100)) / 100.
+        assert_eq!(scale.get_tare(), 999.875 + 100.0);
+        let weight = scale.read().unwrap();
+        assert!((weight - 2.99).abs() < 1e-4, "{weight}");
+    }
+
+    #[test]
+    fn multi_point_calibration_is_used_by_read() {
+        let mut scale = Scale::<i32, f32, 4>::default();
+        let mut capture = |raw, value| {
+            for _ in 0..4 {
+                scale.push(raw);
+            }
+            scale.capture_point(value)
+        };
+        assert_eq!(capture(1_000, 0.0), Ok(()));
+        assert_eq!(capture(2_000, 100.0), Ok(()));
+        assert_eq!(capture(5_900, 500.0), Ok(()));
+        assert_eq!(scale.get_calibration().len(), 3);
+
+        for _ in 0..4 {
+            scale.push(3_950);
+        }
+        assert_eq!(scale.read(), Ok(300.0));
+
+        // Single-point calibration starts the table over
+        scale.capture_unit(300.0).unwrap();
+        assert_eq!(scale.get_calibration().len(), 2);
+        assert_eq!(scale.read(), Ok(300.0));
+    }
+
+    #[test]
+    fn points_are_added_to_single_point_calibration() {
+        let mut scale = Scale::<i32, f32, 4>::default();
+        let push = |scale: &mut Scale<i32, f32, 4>, raw| {
+            for _ in 0..4 {
+                scale.push(raw);
+            }
+        };
+        push(&mut scale, 1_000);
+        scale.capture_tare().unwrap();
+        push(&mut scale, 2_000);
+        scale.capture_unit(100.0).unwrap();
+        assert_eq!(scale.get_unit(), 10.0);
+        push(&mut scale, 5_900);
+        scale.capture_point(500.0).unwrap();
+
+        let points = scale.get_calibration().points();
+        assert_eq!(
+            points,
+            [(0.0, 0.0), (1_000.0, 100.0), (4_900.0, 500.0)]
+                .map(|(raw, value)| CalibrationPoint { raw, value })
+        );
+        push(&mut scale, 3_950);
+        assert_eq!(scale.read(), Ok(300.0));
+    }
 }
@@ -0,0 +1,38 @@
+//! FIR filter taps for [`crate::scale::Scale`].
+//!
+//! Designed with `filter/fir.m` for the NAU7802 running at 20 SPS
+//! (`f_s = 20`, `f1 = 1e-3`, `order = 20`) and exported as Q16 fixed point
+//! numbers. The filter strength selects the stop band edge: `f2 = 3.0`
+//! for [`FilterStrength::Low`], `1.8` for [`FilterStrength::Medium`]
+//! and `1.0` for [`FilterStrength::High`].
+
+use stuff::signal::Fir;
+
+use crate::settings::FilterStrength;
+
+pub const SCALE_FIR_TAP_COUNT: usize = 21;
+
+pub const SCALE_FIR_TAPS_LOW: [i64; SCALE_FIR_TAP_COUNT] = [
+    27, 125, 367, 837, 1599, 2665, 3964, 5338, 6566, 7419, 7725, 7419, 6566, 5338, 3964, 2665,
+    1599, 837, 367, 125, 27,
+];
+
+pub const SCALE_FIR_TAPS: [i64; SCALE_FIR_TAP_COUNT] = [
+    256, 585, 1067, 1699, 2454, 3283, 4120, 4885, 5500, 5900, 6038, 5900, 5500, 4885, 4120, 3283,
+    2454, 1699, 1067, 585, 256,
+];
+
+pub const SCALE_FIR_TAPS_HIGH: [i64; SCALE_FIR_TAP_COUNT] = [
+    1210, 1631, 2078, 2535, 2984, 3405, 3780, 4091, 4325, 4470, 4519, 4470, 4325, 4091, 3780, 3405,
+    2984, 2535, 2078, 1631, 1210,
+];
+
+pub type ScaleFir = Fir<i64, SCALE_FIR_TAP_COUNT>;
+
+pub fn scale_fir(strength: FilterStrength) -> ScaleFir {
+    Fir::new(match strength {
+        FilterStrength::Low => SCALE_FIR_TAPS_LOW,
+        FilterStrength::Medium => SCALE_FIR_TAPS,
+        FilterStrength::High => SCALE_FIR_TAPS_HIGH,
+    })
+}
@@ -0,0 +1,612 @@
+use alloc::{boxed::Box, format, string::String};
+
+use crate::{
+    button::ButtonEvent,
+    common::{AppMessage, AppState, Duration, InputEvent},
+    dose::DoseMenu,
+    profile::ProfileMenu,
+    recipe::RECIPE_COUNT,
+    terminal::Terminal,
+    units::WeightUnit,
+    view_stack::{View, ViewContext},
+};
+use stuff::mq::MessageProcessingStatus;
+
+pub const SETTINGS_SIZE: usize = 10;
+
+const BRIGHTNESS_LEVELS: u8 = 5;
+const AUTO_OFF_MINUTES: [u8; 6] = [0, 1, 2, 5, 10, 30];
+const POUR_SETTLE_SECONDS: [u8; 6] = [0, 3, 5, 10, 30, 60];
+const SAMPLE_SIZES: [u8; 5] = [5, 10, 20, 50, 100];
+const DIVISION_HUNDREDTHS: [u8; 5] = [1, 5, 10, 50, 100];
+/// The number of items that fit the screen between the title and the footer.
+const VISIBLE_ITEMS: usize = 6;
+
+/// User-adjustable options.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Settings {
+    pub unit: WeightUnit,
+    /// Display brightness level, from 1 to 5.
+    pub brightness: u8,
+    pub filter_strength: FilterStrength,
+    /// Turn the display off after the given number of minutes without
+    /// activity, 0 to never turn it off.
+    pub auto_off_minutes: u8,
+    /// Track the zero point automatically.
+    pub auto_tare: bool,
+    /// Start the stopwatch when pouring begins after a tare and stop it
+    /// once the weight has been stable for the given number of seconds,
+    /// 0 to start and stop it manually.
+    pub pour_settle_seconds: u8,
+    /// The main screen.
+    pub mode: Mode,
+    /// The index of the recipe followed in [`Mode::Recipe`].
+    pub recipe: u8,
+    /// The number of reference pieces of [`Mode::Count`].
+    pub sample_size: u8,
+    /// The step of the weight shown in grams, in hundredths of a gram.
+    pub division_hundredths: u8,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            unit: WeightUnit::Grams,
+            brightness: 3,
+            filter_strength: FilterStrength::Medium,
+            auto_off_minutes: 5,
+            auto_tare: true,
+            pour_settle_seconds: 0,
+            mode: Mode::Normal,
+            recipe: 0,
+            sample_size: 10,
+            division_hundredths: 10,
+        }
+    }
+}
+
+impl Settings {
+    pub fn to_bytes(&self) -> [u8; SETTINGS_SIZE] {
+        [
+            self.unit.index(),
+            self.brightness,
+            self.filter_strength as u8,
+            self.auto_off_minutes,
+            self.auto_tare as u8,
+            self.pour_settle_seconds,
+            self.mode as u8,
+            self.recipe,
+            self.sample_size,
+            self.division_hundredths,
+        ]
+    }
+
+    /// Invalid values (e.g. read from the erased flash) are replaced
+    /// with the defaults individually.
+    pub fn from_bytes(bytes: [u8; SETTINGS_SIZE]) -> Self {
+        let default = Self::default();
+        let [unit, brightness, filter_strength, auto_off_minutes, auto_tare, ..] = bytes;
+        let [.., pour_settle_seconds, mode, recipe, sample_size, division_hundredths] = bytes;
+        Self {
+            unit: WeightUnit::from_index(unit).unwrap_or(default.unit),
+            brightness: if (1..=BRIGHTNESS_LEVELS).contains(&brightness) {
+                brightness
+            } else {
+                default.brightness
+            },
+            filter_strength: FilterStrength::from_index(filter_strength)
+                .unwrap_or(default.filter_strength),
+            auto_off_minutes: if AUTO_OFF_MINUTES.contains(&auto_off_minutes) {
+                auto_off_minutes
+            } else {
+                default.auto_off_minutes
+            },
+            auto_tare: match auto_tare {
+                0 => false,
+                1 => true,
+                _ => default.auto_tare,
+            },
+            pour_settle_seconds: if POUR_SETTLE_SECONDS.contains(&pour_settle_seconds) {
+                pour_settle_seconds
+            } else {
+                default.pour_settle_seconds
+            },
+            mode: Mode::from_index(mode).unwrap_or(default.mode),
+            recipe: if (recipe as usize) < RECIPE_COUNT {
+                recipe
+            } else {
+                default.recipe
+            },
+            sample_size: if SAMPLE_SIZES.contains(&sample_size) {
+                sample_size
+            } else {
+                default.sample_size
+            },
+            division_hundredths: if DIVISION_HUNDREDTHS.contains(&division_hundredths) {
+                division_hundredths
+            } else {
+                default.division_hundredths
+            },
+        }
+    }
+
+    pub fn auto_off_timeout(&self) -> Option<Duration> {
+        if self.auto_off_minutes > 0 {
+            Some(Duration::minutes(self.auto_off_minutes as u64))
+        } else {
+            None
+        }
+    }
+
+    /// The step of the weight shown in grams, cf. [`WeightUnit::division`].
+    pub fn division(&self) -> f32 {
+        self.division_hundredths as f32 / 100.0
+    }
+
+    pub fn pour_settle_time(&self) -> Option<Duration> {
+        if self.pour_settle_seconds > 0 {
+            Some(Duration::secs(self.pour_settle_seconds as u64))
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Mode {
+    /// The weight and a stopwatch.
+    Normal,
+    /// A shot timer, see [`crate::espresso::EspressoView`].
+    Espresso,
+    /// Brewing steps, see [`crate::recipe::RecipeView`].
+    Recipe,
+    /// Weighing up to a target, see [`crate::target::TargetView`].
+    Target,
+    /// Portions classified against limits, see [`crate::check::CheckView`].
+    Check,
+    /// Pieces counted by weight, see [`crate::count::CountView`].
+    Count,
+    /// The weight relative to a reference, see
+    /// [`crate::percent::PercentView`].
+    Percent,
+}
+
+impl Mode {
+    const ALL: [Mode; 7] = [
+        Mode::Normal,
+        Mode::Espresso,
+        Mode::Recipe,
+        Mode::Target,
+        Mode::Check,
+        Mode::Count,
+        Mode::Percent,
+    ];
+
+    fn from_index(i: u8) -> Option<Self> {
+        Self::ALL.get(i as usize).copied()
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            Mode::Normal => "NORMAL",
+            Mode::Espresso => "ESPRESSO",
+            Mode::Recipe => "RECIPE",
+            Mode::Target => "TARGET",
+            Mode::Check => "CHECK",
+            Mode::Count => "COUNT",
+            Mode::Percent => "PERCENT",
+        }
+    }
+}
+
+/// Low-pass filter strength, see [`crate::scale_fir::scale_fir`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FilterStrength {
+    Low,
+    Medium,
+    High,
+}
+
+impl FilterStrength {
+    const ALL: [FilterStrength; 3] = [
+        FilterStrength::Low,
+        FilterStrength::Medium,
+        FilterStrength::High,
+    ];
+
+    fn from_index(i: u8) -> Option<Self> {
+        Self::ALL.get(i as usize).copied()
+    }
+}
+
+/// Settings menu.
+///
+/// Button A moves to the next item, button B to the previous one,
+/// a long press on button A selects the item for editing. While editing,
+/// the buttons change the value and a long press on button A goes back
+/// to the items. A long press on button B closes the menu.
+///
+/// The dose and the profile items open the [`DoseMenu`] and the
+/// [`ProfileMenu`] instead of being edited.
+///
+/// The changes are applied immediately and saved when the menu is closed.
+pub struct SettingsMenu {
+    initial: Settings,
+    settings: Settings,
+    index: usize,
+    is_editing: bool,
+}
+
+#[derive(Clone, Copy)]
+enum Item {
+    Unit,
+    Brightness,
+    FilterStrength,
+    Division,
+    AutoOff,
+    AutoTare,
+    PourSettle,
+    Mode,
+    Recipe,
+    SampleSize,
+    Dose,
+    Profile,
+}
+
+const ITEMS: [Item; 12] = [
+    Item::Unit,
+    Item::Brightness,
+    Item::FilterStrength,
+    Item::Division,
+    Item::AutoOff,
+    Item::AutoTare,
+    Item::PourSettle,
+    Item::Mode,
+    Item::Recipe,
+    Item::SampleSize,
+    Item::Dose,
+    Item::Profile,
+];
+
+impl Item {
+    fn label(&self) -> &'static str {
+        match self {
+            Item::Unit => "UNITS",
+            Item::Brightness => "BRIGHT",
+            Item::FilterStrength => "FILTER",
+            Item::Division => "DIVISION",
+            Item::AutoOff => "AUTO-OFF",
+            Item::AutoTare => "AUTO-TARE",
+            Item::PourSettle => "AUTO-TIME",
+            Item::Mode => "MODE",
+            Item::Recipe => "RECIPE",
+            Item::SampleSize => "SAMPLE",
+            Item::Dose => "DOSE",
+            Item::Profile => "PROFILE",
+        }
+    }
+
+    fn value(&self, settings: &Settings, state: &AppState) -> String {
+        match self {
+            Item::Unit => settings.unit.name().into(),
+            Item::Brightness => format!("{}", settings.brightness),
+            Item::FilterStrength => match settings.filter_strength {
+                FilterStrength::Low => "LOW",
+                FilterStrength::Medium => "MEDIUM",
+                FilterStrength::High => "HIGH",
+            }
+            .into(),
+            Item::Division => format!("{} g", settings.division()),
+            Item::AutoOff => match settings.auto_off_minutes {
+                0 => "NEVER".into(),
+                m => format!("{m} min"),
+            },
+            Item::AutoTare => if settings.auto_tare { "ON" } else { "OFF" }.into(),
+            Item::PourSettle => match settings.pour_settle_seconds {
+                0 => "OFF".into(),
+                s => format!("{s} s"),
+            },
+            Item::Mode => settings.mode.label().into(),
+            Item::Recipe => state.recipes[settings.recipe as usize].name.as_str().into(),
+            Item::SampleSize => format!("{} PCS", settings.sample_size),
+            Item::Dose => match state.dose {
+                Some(dose) => format!("{dose:.1} g"),
+                None => "OFF".into(),
+            },
+            Item::Profile => state.profile_names[state.active_profile].as_str().into(),
+        }
+    }
+
+    /// Select the next (or the previous) value, wrapping around.
+    fn change(&self, settings: &mut Settings, forward: bool) {
+        fn step<T: Copy + PartialEq>(all: &[T], value: T, forward: bool) -> T {
+            let i = all.iter().position(|&x| x == value).unwrap_or(0);
+            let n = all.len();
+            all[if forward {
+                (i + 1) % n
+            } else {
+                (i + n - 1) % n
+            }]
+        }
+        match self {
+            Item::Unit => settings.unit = step(&WeightUnit::ALL, settings.unit, forward),
+            Item::Brightness => {
+                settings.brightness = if forward {
+                    settings.brightness % BRIGHTNESS_LEVELS + 1
+                } else {
+                    (settings.brightness + BRIGHTNESS_LEVELS - 2) % BRIGHTNESS_LEVELS + 1
+                }
+            }
+            Item::FilterStrength => {
+                settings.filter_strength =
+                    step(&FilterStrength::ALL, settings.filter_strength, forward)
+            }
+            Item::Division => {
+                settings.division_hundredths =
+                    step(&DIVISION_HUNDREDTHS, settings.division_hundredths, forward)
+            }
+            Item::AutoOff => {
+                settings.auto_off_minutes =
+                    step(&AUTO_OFF_MINUTES, settings.auto_off_minutes, forward)
+            }
+            Item::AutoTare => settings.auto_tare = !settings.auto_tare,
+            Item::PourSettle => {
+                settings.pour_settle_seconds =
+                    step(&POUR_SETTLE_SECONDS, settings.pour_settle_seconds, forward)
+            }
+            Item::Mode => settings.mode = step(&Mode::ALL, settings.mode, forward),
+            Item::Recipe => {
+                let n = RECIPE_COUNT as u8;
+                settings.recipe = if forward {
+                    (settings.recipe + 1) % n
+                } else {
+                    (settings.recipe + n - 1) % n
+                }
+            }
+            Item::SampleSize => {
+                settings.sample_size = step(&SAMPLE_SIZES, settings.sample_size, forward)
+            }
+            Item::Dose | Item::Profile => {}
+        }
+    }
+}
+
+impl SettingsMenu {
+    pub fn new(settings: Settings) -> Self {
+        Self {
+            initial: settings,
+            settings,
+            index: 0,
+            is_editing: false,
+        }
+    }
+
+    fn handle_input(&mut self, e: &InputEvent, cx: &mut ViewContext) {
+        match e {
+            InputEvent::ButtonA(ButtonEvent::Press) | InputEvent::ButtonB(ButtonEvent::Press) => {
+                let forward = matches!(e, InputEvent::ButtonA(_));
+                if self.is_editing {
+                    ITEMS[self.index].change(&mut self.settings, forward);
+                    cx.send(AppMessage::ChangeSettings(self.settings));
+                } else if forward {
+                    self.index = (self.index + 1) % ITEMS.len();
+                } else {
+                    self.index = (self.index + ITEMS.len() - 1) % ITEMS.len();
+                }
+            }
+            InputEvent::ButtonA(ButtonEvent::LongPress) => match ITEMS[self.index] {
+                Item::Dose => cx.push_view(Box::new(DoseMenu::new(cx.state.dose))),
+                Item::Profile => cx.push_view(Box::new(ProfileMenu::new(
+                    cx.state.profile_names,
+                    cx.state.active_profile,
+                ))),
+                _ => self.is_editing = !self.is_editing,
+            },
+            InputEvent::ButtonB(ButtonEvent::LongPress) => {
+                if self.settings != self.initial {
+                    cx.send(AppMessage::SaveSettings);
+                }
+                cx.pop_view();
+            }
+        }
+    }
+}
+
+impl View for SettingsMenu {
+    fn handle_message(&mut self, m: &AppMessage, cx: &mut ViewContext) -> MessageProcessingStatus {
+        if let AppMessage::InputEvent(e) = m {
+            self.handle_input(e, cx);
+            MessageProcessingStatus::Processed
+        } else {
+            MessageProcessingStatus::Ignored
+        }
+    }
+
+    fn render(&mut self, terminal: &mut dyn Terminal, state: &AppState) -> core::fmt::Result {
+        terminal.set_position(0, 0)?;
+        terminal.write_str("SETTINGS\n")?;
+        // Scroll just enough to show the selected item
+        let top = self.index.saturating_sub(VISIBLE_ITEMS - 1);
+        for (i, item) in ITEMS.iter().enumerate().skip(top).take(VISIBLE_ITEMS) {
+            let marker = match (i == self.index, self.is_editing) {
+                (true, false) => '>',
+                (true, true) => '*',
+                (false, _) => ' ',
+            };
+            // The value is right-aligned, a long one takes space from the label
+            let label = item.label();
+            terminal.write_fmt(format_args!(
+                "{marker}{label}{:>1$}\n",
+                item.value(&self.settings, state),
+                15 - label.len()
+            ))?;
+        }
+        terminal.write_str("HOLD B: EXIT")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        common::AppContext,
+        name::Name,
+        testing::{input, show, take_messages},
+    };
+    use stuff::run_loop::{Task, TaskStatus};
+
+    #[test]
+    fn settings_round_trip_through_bytes() {
+        let settings = Settings {
+            unit: WeightUnit::Ounces,
+            brightness: 5,
+            filter_strength: FilterStrength::High,
+            auto_off_minutes: 0,
+            auto_tare: false,
+            pour_settle_seconds: 10,
+            mode: Mode::Espresso,
+            recipe: 1,
+            sample_size: 50,
+            division_hundredths: 5,
+        };
+        assert_eq!(Settings::from_bytes(settings.to_bytes()), settings);
+    }
+
+    #[test]
+    fn invalid_bytes_are_replaced_with_defaults() {
+        assert_eq!(
+            Settings::from_bytes([0xff; SETTINGS_SIZE]),
+            Settings::default()
+        );
+        let settings = Settings::from_bytes([1, 0, 2, 3, 1, 10, 0xff, 0xff, 20, 7]);
+        assert_eq!(settings.unit, WeightUnit::Ounces);
+        assert_eq!(settings.brightness, Settings::default().brightness);
+        assert_eq!(settings.filter_strength, FilterStrength::High);
+        assert_eq!(
+            settings.auto_off_minutes,
+            Settings::default().auto_off_minutes
+        );
+        assert_eq!(settings.pour_settle_seconds, 10);
+        assert_eq!(settings.mode, Settings::default().mode);
+        assert_eq!(settings.recipe, Settings::default().recipe);
+        assert_eq!(settings.sample_size, 20);
+        assert_eq!(
+            settings.division_hundredths,
+            Settings::default().division_hundredths
+        );
+    }
+
+    #[test]
+    fn values_are_changed_and_saved() {
+        let mut cx = AppContext::default();
+        let (mut stack, terminal) = show(Box::new(SettingsMenu::new(Settings::default())));
+
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(0), "SETTINGS");
+        assert_eq!(terminal.borrow().line(1), ">UNITS         g");
+        assert_eq!(terminal.borrow().line(3), " FILTER   MEDIUM");
+
+        // Select the brightness and make it one level dimmer
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::Press));
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::LongPress));
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::Press));
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(2), "*BRIGHT        2");
+        let expected = Settings {
+            brightness: 2,
+            ..Default::default()
+        };
+        assert_eq!(
+            take_messages(&mut cx),
+            [AppMessage::ChangeSettings(expected)]
+        );
+
+        // Back to the items, then to the auto-tare (wrapping around)
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::LongPress));
+        for _ in 0..8 {
+            input(&mut cx, InputEvent::ButtonB(ButtonEvent::Press));
+        }
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(5), " AUTO-OFF  5 min");
+        assert_eq!(terminal.borrow().line(6), ">AUTO-TARE    ON");
+
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::LongPress));
+        assert!(matches!(stack.run(&mut cx), TaskStatus::Done));
+        assert_eq!(take_messages(&mut cx), [AppMessage::SaveSettings]);
+    }
+
+    #[test]
+    fn values_wrap_around() {
+        let mut settings = Settings::default();
+        Item::AutoOff.change(&mut settings, false);
+        Item::AutoOff.change(&mut settings, false);
+        Item::AutoOff.change(&mut settings, false);
+        Item::AutoOff.change(&mut settings, false);
+        assert_eq!(settings.auto_off_minutes, 30);
+        Item::PourSettle.change(&mut settings, false);
+        assert_eq!(settings.pour_settle_seconds, 60);
+        for _ in 0..BRIGHTNESS_LEVELS {
+            Item::Brightness.change(&mut settings, true);
+        }
+        assert_eq!(settings.brightness, Settings::default().brightness);
+    }
+
+    #[test]
+    fn unchanged_settings_are_not_saved() {
+        let mut cx = AppContext::default();
+        let (mut stack, _) = show(Box::new(SettingsMenu::new(Settings::default())));
+
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::LongPress));
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::Press));
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::Press));
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::LongPress));
+        stack.run(&mut cx);
+        assert_eq!(
+            take_messages(&mut cx),
+            [
+                AppMessage::ChangeSettings(Settings {
+                    unit: WeightUnit::Ounces,
+                    ..Default::default()
+                }),
+                AppMessage::ChangeSettings(Settings::default()),
+            ]
+        );
+    }
+
+    #[test]
+    fn dose_item_opens_the_dose_menu() {
+        let mut cx = AppContext::default();
+        cx.state.dose = Some(18.5);
+        let (mut stack, terminal) = show(Box::new(SettingsMenu::new(Settings::default())));
+
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::Press));
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::Press));
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(6), ">DOSE     18.5 g");
+
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::LongPress));
+        stack.run(&mut cx);
+        assert_eq!(stack.len(), 2);
+        assert_eq!(terminal.borrow().line(2), "DOSE      18.5 g");
+    }
+
+    #[test]
+    fn profile_item_opens_the_profile_menu() {
+        let mut cx = AppContext::default();
+        cx.state.profile_names[1] = Name::from_bytes(*b"KITCHEN ").unwrap();
+        cx.state.active_profile = 1;
+        let (mut stack, terminal) = show(Box::new(SettingsMenu::new(Settings::default())));
+
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::Press));
+        stack.run(&mut cx);
+        // Scrolled by six items
+        assert_eq!(terminal.borrow().line(1), " AUTO-TIME   OFF");
+        assert_eq!(terminal.borrow().line(6), ">PROFILE KITCHEN");
+
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::LongPress));
+        stack.run(&mut cx);
+        assert_eq!(stack.len(), 2);
+        assert_eq!(terminal.borrow().line(0), "PROFILES");
+        assert_eq!(terminal.borrow().line(3), ">KITCHEN  ACTIVE");
+    }
+}
@@ -0,0 +1,425 @@
+use alloc::{boxed::Box, format, string::String};
+
+use crate::{
+    button::ButtonEvent,
+    common::{AppMessage, AppState, InputEvent, Instant},
+    dashboard::{handle_long_press, switch_mode},
+    entry::{Step, Tenths},
+    settings::Mode,
+    terminal::Terminal,
+    units::weight_row,
+    view_stack::{View, ViewContext},
+};
+use stuff::mq::MessageProcessingStatus;
+
+/// The status changes back only once the weight is this many resolution
+/// steps of the scale past the boundary, so that the alert doesn't flicker.
+const HYSTERESIS_STEPS: f32 = 2.0;
+
+/// The period of the blinking overshoot alert, in milliseconds.
+const BLINK_PERIOD_MS: u64 = 500;
+
+/// The largest target and tolerance, in tenths of a gram.
+const MAX_TARGET: u16 = 50_000;
+const MAX_TOLERANCE: u16 = 999;
+
+/// A weight to reach.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Target {
+    /// The weight to reach, in grams after the tare.
+    pub weight: f32,
+    /// How far the weight may end up from the target, in grams.
+    pub tolerance: f32,
+}
+
+impl Default for Target {
+    fn default() -> Self {
+        Self {
+            weight: 18.0,
+            tolerance: 0.2,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TargetStatus {
+    Below,
+    /// The weight is within the tolerance of the target.
+    Reached,
+    /// The weight is past the target by more than the tolerance.
+    Over,
+}
+
+/// Follows the readouts toward a target.
+pub struct TargetTracker {
+    target: Target,
+    status: TargetStatus,
+}
+
+impl TargetTracker {
+    pub fn new(target: Target) -> Self {
+        Self {
+            target,
+            status: TargetStatus::Below,
+        }
+    }
+
+    pub fn target(&self) -> &Target {
+        &self.target
+    }
+
+    pub fn status(&self) -> TargetStatus {
+        self.status
+    }
+
+    /// The weight left to the target, negative once past it.
+    pub fn remaining(&self, weight: f32) -> f32 {
+        self.target.weight - weight
+    }
+
+    /// The progress toward the target, from 0 to 1.
+    pub fn progress(&self, weight: f32) -> f32 {
+        if self.target.weight > 0.0 {
+            (weight / self.target.weight).clamp(0.0, 1.0)
+        } else {
+            1.0
+        }
+    }
+
+    /// Feed a readout of a scale of the given resolution, in grams. Returns
+    /// the new status if it has changed.
+    pub fn update(&mut self, weight: f32, resolution: f32) -> Option<TargetStatus> {
+        let Target {
+            weight: target,
+            tolerance,
+        } = self.target;
+        let hysteresis = resolution * HYSTERESIS_STEPS;
+        // Leaving the current status takes the hysteresis, entering doesn't
+        let is_over = match self.status {
+            TargetStatus::Over => weight > target + tolerance - hysteresis,
+            _ => weight > target + tolerance,
+        };
+        let is_below = match self.status {
+            TargetStatus::Below => weight < target - tolerance,
+            _ => weight < target - tolerance - hysteresis,
+        };
+        let status = if is_over {
+            TargetStatus::Over
+        } else if is_below {
+            TargetStatus::Below
+        } else {
+            TargetStatus::Reached
+        };
+        (status != self.status).then(|| {
+            self.status = status;
+            status
+        })
+    }
+}
+
+/// A bar of the given number of characters filled to the progress, from 0
+/// to 1, e.g. `[######----]`.
+pub fn progress_bar(progress: f32, width: usize) -> String {
+    let cells = width.saturating_sub(2);
+    let filled = libm::roundf(progress.clamp(0.0, 1.0) * cells as f32) as usize;
+    format!("[{:#<filled$}{:-<2$}]", "", "", cells - filled)
+}
+
+/// The main screen of [`Mode::Target`].
+///
+/// Button A tares, button B opens the [`TargetMenu`]. The long presses are
+/// the same as on the dashboard.
+pub struct TargetView {
+    get_instant: fn() -> Instant,
+    tracker: TargetTracker,
+}
+
+impl TargetView {
+    pub fn new(get_instant: fn() -> Instant) -> Self {
+        Self {
+            get_instant,
+            tracker: TargetTracker::new(Target::default()),
+        }
+    }
+}
+
+impl View for TargetView {
+    fn handle_message(&mut self, m: &AppMessage, cx: &mut ViewContext) -> MessageProcessingStatus {
+        if let AppMessage::InputEvent(e) = m {
+            match e {
+                InputEvent::ButtonA(ButtonEvent::Press) => cx.send(AppMessage::Tare),
+                InputEvent::ButtonB(ButtonEvent::Press) => {
+                    cx.push_view(Box::new(TargetMenu::new(cx.state.target)))
+                }
+                e => handle_long_press(e, cx),
+            }
+            MessageProcessingStatus::Processed
+        } else {
+            MessageProcessingStatus::Ignored
+        }
+    }
+
+    fn render(&mut self, terminal: &mut dyn Terminal, state: &AppState) -> core::fmt::Result {
+        let unit = state.settings.unit;
+        let division = unit.division(state.settings.division());
+        let remaining = self.tracker.remaining(state.weight);
+        let row = |label, grams| weight_row(label, grams, unit, division);
+        let left = if remaining >= 0.0 {
+            row("LEFT", remaining)
+        } else {
+            row("OVER", -remaining)
+        };
+        terminal.set_position(0, 0)?;
+        terminal.write_fmt(format_args!(
+            "{}\n\n{}\n{left}\n\n{}\n\n",
+            row("TARGET", self.tracker.target().weight),
+            row("WEIGHT", state.weight),
+            progress_bar(self.tracker.progress(state.weight), 16),
+        ))?;
+        let is_blink_on =
+            (self.get_instant)().duration_since_epoch().to_millis() / BLINK_PERIOD_MS % 2 == 0;
+        let alert = match self.tracker.status() {
+            TargetStatus::Below => "",
+            TargetStatus::Reached => "REACHED",
+            TargetStatus::Over if is_blink_on => "!! OVERSHOT !!",
+            TargetStatus::Over => "",
+        };
+        terminal.write_fmt(format_args!("{alert:^16}"))
+    }
+
+    fn update(&mut self, cx: &mut ViewContext) {
+        if switch_mode(Mode::Target, self.get_instant, cx) {
+            return;
+        }
+        if *self.tracker.target() != cx.state.target {
+            self.tracker = TargetTracker::new(cx.state.target);
+        }
+        self.tracker.update(cx.state.weight, cx.state.resolution);
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Target,
+    Tolerance,
+    Step,
+}
+
+/// Target menu.
+///
+/// A long press on button A selects the next field, buttons A and B change
+/// it. A long press on button B sets the target and closes the menu.
+pub struct TargetMenu {
+    initial: Target,
+    target: Tenths,
+    tolerance: Tenths,
+    step: Step,
+    field: Field,
+}
+
+impl TargetMenu {
+    pub fn new(target: Target) -> Self {
+        Self {
+            initial: target,
+            target: Tenths::from_grams(target.weight, 1, MAX_TARGET),
+            tolerance: Tenths::from_grams(target.tolerance, 0, MAX_TOLERANCE),
+            step: Step::default(),
+            field: Field::Target,
+        }
+    }
+
+    fn change(&mut self, forward: bool) {
+        match self.field {
+            Field::Target => self.target.change(self.step, forward),
+            Field::Tolerance => self.tolerance.change(self.step, forward),
+            Field::Step => self.step.change(forward),
+        }
+    }
+
+    fn handle_input(&mut self, e: &InputEvent, cx: &mut ViewContext) {
+        match e {
+            InputEvent::ButtonA(ButtonEvent::Press) => self.change(true),
+            InputEvent::ButtonB(ButtonEvent::Press) => self.change(false),
+            InputEvent::ButtonA(ButtonEvent::LongPress) => {
+                self.field = match self.field {
+                    Field::Target => Field::Tolerance,
+                    Field::Tolerance => Field::Step,
+                    Field::Step => Field::Target,
+                }
+            }
+            InputEvent::ButtonB(ButtonEvent::LongPress) => {
+                let target = Target {
+                    weight: self.target.grams(),
+                    tolerance: self.tolerance.grams(),
+                };
+                if target != self.initial {
+                    cx.send(AppMessage::SetTarget(target));
+                }
+                cx.pop_view();
+            }
+        }
+    }
+}
+
+impl View for TargetMenu {
+    fn handle_message(&mut self, m: &AppMessage, cx: &mut ViewContext) -> MessageProcessingStatus {
+        if let AppMessage::InputEvent(e) = m {
+            self.handle_input(e, cx);
+            MessageProcessingStatus::Processed
+        } else {
+            MessageProcessingStatus::Ignored
+        }
+    }
+
+    fn render(&mut self, terminal: &mut dyn Terminal, _state: &AppState) -> core::fmt::Result {
+        terminal.set_position(0, 0)?;
+        terminal.write_str("TARGET\n\n")?;
+        let rows = [
+            (Field::Target, "TARGET", self.target.grams()),
+            (Field::Tolerance, "TOLER.", self.tolerance.grams()),
+            (Field::Step, "STEP", self.step.grams()),
+        ];
+        for (field, label, grams) in rows {
+            let marker = if field == self.field { '>' } else { ' ' };
+            terminal.write_fmt(format_args!("{marker}{label:<6}{grams:>7.1} g\n"))?;
+        }
+        terminal.write_str("\nA/B: +/-\nHOLD A: NEXT\nHOLD B: DONE")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::{AtomicU64, Ordering};
+
+    use super::*;
+    use crate::{
+        common::AppContext,
+        testing::{input, show, take_messages},
+    };
+    use stuff::run_loop::{Task, TaskStatus};
+
+    const RESOLUTION: f32 = 0.05;
+
+    fn tracker() -> TargetTracker {
+        TargetTracker::new(Target {
+            weight: 20.0,
+            tolerance: 0.5,
+        })
+    }
+
+    #[test]
+    fn target_is_reached_and_overshot() {
+        let mut t = tracker();
+        assert_eq!(t.update(10.0, RESOLUTION), None);
+        assert_eq!(t.remaining(10.0), 10.0);
+        assert_eq!(t.progress(10.0), 0.5);
+        assert_eq!(t.update(19.6, RESOLUTION), Some(TargetStatus::Reached));
+        assert_eq!(t.update(20.5, RESOLUTION), None);
+        assert_eq!(t.update(20.6, RESOLUTION), Some(TargetStatus::Over));
+        assert_eq!(t.progress(20.6), 1.0);
+        // E.g. the cup taken away
+        assert_eq!(t.update(-150.0, RESOLUTION), Some(TargetStatus::Below));
+        assert_eq!(t.update(25.0, RESOLUTION), Some(TargetStatus::Over));
+    }
+
+    #[test]
+    fn status_does_not_flicker_at_the_boundary() {
+        let mut t = tracker();
+        assert_eq!(t.update(19.5, RESOLUTION), Some(TargetStatus::Reached));
+        assert_eq!(t.update(19.45, RESOLUTION), None);
+        assert_eq!(t.update(19.35, RESOLUTION), Some(TargetStatus::Below));
+        assert_eq!(t.update(20.55, RESOLUTION), Some(TargetStatus::Over));
+        assert_eq!(t.update(20.45, RESOLUTION), None);
+        assert_eq!(t.update(20.35, RESOLUTION), Some(TargetStatus::Reached));
+    }
+
+    #[test]
+    fn hysteresis_follows_the_resolution() {
+        let mut t = tracker();
+        assert_eq!(t.update(20.6, 0.5), Some(TargetStatus::Over));
+        assert_eq!(t.update(19.8, 0.5), None);
+        assert_eq!(t.update(19.4, 0.5), Some(TargetStatus::Reached));
+    }
+
+    #[test]
+    fn progress_bar_is_filled() {
+        assert_eq!(progress_bar(0.0, 8), "[------]");
+        assert_eq!(progress_bar(0.5, 8), "[###---]");
+        assert_eq!(progress_bar(1.5, 8), "[######]");
+    }
+
+    #[test]
+    fn progress_is_shown() {
+        static NOW: AtomicU64 = AtomicU64::new(0);
+        let mut cx = AppContext::default();
+        cx.state.settings.mode = Mode::Target;
+        cx.state.target = Target {
+            weight: 20.0,
+            tolerance: 0.5,
+        };
+        let (mut stack, terminal) = show(Box::new(TargetView::new(|| {
+            Instant::from_ticks(NOW.load(Ordering::Relaxed))
+        })));
+
+        cx.state.weight = 15.0;
+        stack.run(&mut cx);
+        stack.run(&mut cx);
+        let lines: [String; 8] = core::array::from_fn(|i| terminal.borrow().line(i));
+        assert_eq!(
+            lines,
+            [
+                "TARGET    20.0 g",
+                "",
+                "WEIGHT    15.0 g",
+                "LEFT       5.0 g",
+                "",
+                "[###########---]",
+                "",
+                "",
+            ]
+        );
+
+        cx.state.weight = 20.2;
+        stack.run(&mut cx);
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(7), "    REACHED");
+
+        cx.state.weight = 21.0;
+        stack.run(&mut cx);
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(3), "OVER       1.0 g");
+        assert_eq!(terminal.borrow().line(7), " !! OVERSHOT !!");
+        NOW.store(BLINK_PERIOD_MS * 1_000, Ordering::Relaxed);
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(7), "");
+    }
+
+    #[test]
+    fn target_is_entered() {
+        let mut cx = AppContext::default();
+        let (mut stack, terminal) = show(Box::new(TargetMenu::new(Target::default())));
+
+        // 10 g steps
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::LongPress));
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::LongPress));
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::Press));
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::LongPress));
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::Press));
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::Press));
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(2), ">TARGET   38.0 g");
+        assert_eq!(terminal.borrow().line(4), " STEP     10.0 g");
+
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::LongPress));
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::Press));
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::LongPress));
+        assert!(matches!(stack.run(&mut cx), TaskStatus::Done));
+        assert_eq!(
+            take_messages(&mut cx),
+            [AppMessage::SetTarget(Target {
+                weight: 38.0,
+                tolerance: 0.0,
+            })]
+        );
+    }
+}
@@ -0,0 +1,97 @@
+//! The fakes and helpers the tests of the views share.
+
+use alloc::{boxed::Box, rc::Rc, string::String, vec::Vec};
+use core::cell::RefCell;
+
+use crate::{
+    common::{AppContext, AppMessage, InputEvent},
+    terminal::Terminal,
+    view_stack::{View, ViewStack},
+};
+use stuff::mq::MessageProcessingStatus;
+
+/// Push an input event, as the input scanner does.
+pub fn input(cx: &mut AppContext, e: InputEvent) {
+    cx.mq.push(AppMessage::InputEvent(e));
+}
+
+/// Take the messages the views left in the queue for the main loop.
+pub fn take_messages(cx: &mut AppContext) -> Vec<AppMessage> {
+    let mut ms = Vec::new();
+    cx.mq.process(|m, _| {
+        ms.push(m.clone());
+        MessageProcessingStatus::Processed
+    });
+    ms
+}
+
+/// A view stack showing the view, and the terminal it renders to.
+pub fn show(view: Box<dyn View>) -> (ViewStack, Rc<RefCell<FakeTerminal>>) {
+    let terminal = Rc::new(RefCell::new(FakeTerminal::default()));
+    let mut stack = ViewStack::new(terminal.clone());
+    stack.push(view);
+    (stack, terminal)
+}
+
+/// A terminal of the SSD1306 geometry (16×8 characters) that keeps the text
+/// in memory.
+pub struct FakeTerminal {
+    chars: [[char; FakeTerminal::COLUMNS]; FakeTerminal::ROWS],
+    column: usize,
+    row: usize,
+}
+
+impl Default for FakeTerminal {
+    fn default() -> Self {
+        Self {
+            chars: [[' '; Self::COLUMNS]; Self::ROWS],
+            column: 0,
+            row: 0,
+        }
+    }
+}
+
+impl FakeTerminal {
+    pub const COLUMNS: usize = 16;
+    pub const ROWS: usize = 8;
+
+    /// The text of the row without the trailing spaces.
+    pub fn line(&self, row: usize) -> String {
+        let line: String = self.chars[row].iter().collect();
+        line.trim_end().into()
+    }
+}
+
+impl core::fmt::Write for FakeTerminal {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for c in s.chars() {
+            if c == '\n' {
+                self.column = 0;
+                self.row += 1;
+            } else {
+                if self.column == Self::COLUMNS {
+                    self.column = 0;
+                    self.row += 1;
+                }
+                if self.row < Self::ROWS {
+                    self.chars[self.row][self.column] = c;
+                }
+                self.column += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Terminal for FakeTerminal {
+    fn clear(&mut self) -> core::fmt::Result {
+        *self = Self::default();
+        Ok(())
+    }
+
+    fn set_position(&mut self, column: u8, row: u8) -> core::fmt::Result {
+        self.column = column as usize;
+        self.row = row as usize;
+        Ok(())
+    }
+}
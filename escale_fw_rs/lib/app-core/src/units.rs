@@ -0,0 +1,272 @@
+use alloc::{format, string::String};
+
+use crate::quantizer;
+
+const GRAMS_PER_OUNCE: f32 = 28.349_523;
+const OUNCES_PER_POUND: u32 = 16;
+const GRAMS_PER_CARAT: f32 = 0.2;
+
+/// Infinity (of the sign of `x`) if `x` exceeds `abs_max`, so that a value
+/// that doesn't fit the display is shown as `inf` rather than cut off.
+pub fn clamp_inf(x: f32, abs_max: f32) -> f32 {
+    if libm::fabsf(x) > abs_max {
+        if x >= 0.0 {
+            f32::INFINITY
+        } else {
+            f32::NEG_INFINITY
+        }
+    } else {
+        x
+    }
+}
+
+/// An ingredient measured by volume.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Ingredient {
+    Water,
+    Milk,
+    Oil,
+    Honey,
+    Flour,
+    Sugar,
+}
+
+impl Ingredient {
+    /// In g/ml.
+    pub fn density(&self) -> f32 {
+        match self {
+            Ingredient::Water => 1.0,
+            Ingredient::Milk => 1.03,
+            Ingredient::Oil => 0.92,
+            Ingredient::Honey => 1.42,
+            Ingredient::Flour => 0.53,
+            Ingredient::Sugar => 0.85,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WeightUnit {
+    Grams,
+    Ounces,
+    /// Pounds and ounces, converted to ounces except for the readout.
+    PoundsOunces,
+    Carats,
+    /// The volume of the ingredient.
+    Millilitres(Ingredient),
+}
+
+impl WeightUnit {
+    pub const ALL: [WeightUnit; 10] = [
+        WeightUnit::Grams,
+        WeightUnit::Ounces,
+        WeightUnit::PoundsOunces,
+        WeightUnit::Carats,
+        WeightUnit::Millilitres(Ingredient::Water),
+        WeightUnit::Millilitres(Ingredient::Milk),
+        WeightUnit::Millilitres(Ingredient::Oil),
+        WeightUnit::Millilitres(Ingredient::Honey),
+        WeightUnit::Millilitres(Ingredient::Flour),
+        WeightUnit::Millilitres(Ingredient::Sugar),
+    ];
+
+    pub fn from_index(i: u8) -> Option<Self> {
+        Self::ALL.get(i as usize).copied()
+    }
+
+    /// The index in [`Self::ALL`], the stored representation.
+    pub fn index(&self) -> u8 {
+        Self::ALL.iter().position(|u| u == self).unwrap() as u8
+    }
+
+    /// The next unit, wrapping around.
+    pub fn next(&self) -> Self {
+        Self::ALL[(self.index() as usize + 1) % Self::ALL.len()]
+    }
+
+    /// The symbol of the values of [`Self::convert`]. The unit symbols are
+    /// lowercase in every view, unlike the labels.
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            WeightUnit::Grams => "g",
+            WeightUnit::Ounces | WeightUnit::PoundsOunces => "oz",
+            WeightUnit::Carats => "ct",
+            WeightUnit::Millilitres(_) => "ml",
+        }
+    }
+
+    /// The symbol of [`Self::format`].
+    pub fn readout_symbol(&self) -> &'static str {
+        match self {
+            WeightUnit::PoundsOunces => "lb:oz",
+            unit => unit.symbol(),
+        }
+    }
+
+    /// The name shown in the settings.
+    pub fn name(&self) -> &'static str {
+        match self {
+            WeightUnit::Millilitres(ingredient) => match ingredient {
+                Ingredient::Water => "ml WATER",
+                Ingredient::Milk => "ml MILK",
+                Ingredient::Oil => "ml OIL",
+                Ingredient::Honey => "ml HONEY",
+                Ingredient::Flour => "ml FLOUR",
+                Ingredient::Sugar => "ml SUGAR",
+            },
+            unit => unit.readout_symbol(),
+        }
+    }
+
+    /// Convert from grams.
+    pub fn convert(&self, grams: f32) -> f32 {
+        match self {
+            WeightUnit::Grams => grams,
+            WeightUnit::Ounces | WeightUnit::PoundsOunces => grams / GRAMS_PER_OUNCE,
+            WeightUnit::Carats => grams / GRAMS_PER_CARAT,
+            WeightUnit::Millilitres(ingredient) => grams / ingredient.density(),
+        }
+    }
+
+    /// The step of the readout in the unit, given the display division in
+    /// grams, cf. [`crate::settings::Settings::division`]. The other units
+    /// have a fixed one, e.g. 0.1 oz for pounds and ounces.
+    pub fn division(&self, grams_division: f32) -> f32 {
+        match self {
+            WeightUnit::Grams => grams_division,
+            WeightUnit::Ounces => 0.01,
+            WeightUnit::PoundsOunces | WeightUnit::Carats | WeightUnit::Millilitres(_) => 0.1,
+        }
+    }
+
+    /// The readout of a value of [`Self::convert`], at most 8 characters
+    /// long, with the decimals of the division in the unit, cf.
+    /// [`Self::division`], e.g. `1:03.5` for pounds and ounces.
+    pub fn format(&self, value: f32, division: f32) -> String {
+        match self {
+            WeightUnit::Grams | WeightUnit::Ounces => format!(
+                "{:.*}",
+                quantizer::decimals(division),
+                clamp_inf(value, 9_999.99)
+            ),
+            WeightUnit::Carats | WeightUnit::Millilitres(_) => format!(
+                "{:.*}",
+                quantizer::decimals(division),
+                clamp_inf(value, 99_999.9)
+            ),
+            WeightUnit::PoundsOunces => {
+                // Shown as in the other units if there are 1000 pounds or more
+                let max = 1_000.0 * OUNCES_PER_POUND as f32;
+                if value.is_nan() {
+                    return "NaN".into();
+                } else if value >= max {
+                    return "inf".into();
+                } else if value <= -max {
+                    return "-inf".into();
+                }
+                // Rounded first, so that e.g. 15.96 oz is 1:00.0 rather than 0:16.0
+                let tenths = libm::roundf(libm::fabsf(value) * 10.0) as u32;
+                let pounds = tenths / (OUNCES_PER_POUND * 10);
+                let tenths = tenths % (OUNCES_PER_POUND * 10);
+                format!(
+                    "{}{pounds}:{:02}.{}",
+                    if value < 0.0 && (pounds > 0 || tenths > 0) {
+                        "-"
+                    } else {
+                        ""
+                    },
+                    tenths / 10,
+                    tenths % 10,
+                )
+            }
+        }
+    }
+}
+
+/// A row of a view: the label, the weight in grams as shown in the unit
+/// (cf. [`WeightUnit::format`], `division` is in the unit) and its symbol,
+/// 16 columns in total, e.g. `WEIGHT  12.5 oz`. Pounds and ounces are shown
+/// without their symbol, which doesn't fit, the colon tells them apart.
+pub fn weight_row(label: &str, grams: f32, unit: WeightUnit, division: f32) -> String {
+    let value = unit.format(unit.convert(grams), division);
+    match unit {
+        WeightUnit::PoundsOunces => format!("{label:<6}{value:>10}"),
+        unit => {
+            let symbol = unit.symbol();
+            format!(
+                "{label:<6}{value:>width$} {symbol}",
+                width = 9 - symbol.len()
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weight_is_formatted_in_each_unit() {
+        let format = |unit: WeightUnit, grams: f32, grams_division: f32| {
+            unit.format(unit.convert(grams), unit.division(grams_division))
+        };
+        assert_eq!(format(WeightUnit::Grams, 12.345, 0.01), "12.35");
+        assert_eq!(format(WeightUnit::Grams, 12.5, 0.1), "12.5");
+        assert_eq!(format(WeightUnit::Grams, 12.0, 1.0), "12");
+        assert_eq!(format(WeightUnit::Ounces, 283.5, 1.0), "10.00");
+        assert_eq!(format(WeightUnit::Carats, 1.0, 0.01), "5.0");
+        assert_eq!(
+            format(WeightUnit::Millilitres(Ingredient::Milk), 257.5, 0.01),
+            "250.0"
+        );
+        assert_eq!(format(WeightUnit::Grams, 12_345.0, 0.01), "inf");
+        assert_eq!(format(WeightUnit::Grams, -12_345.0, 0.01), "-inf");
+        assert_eq!(format(WeightUnit::Carats, 20_000.0, 0.01), "inf");
+    }
+
+    #[test]
+    fn pounds_and_ounces_are_split() {
+        let unit = WeightUnit::PoundsOunces;
+        assert_eq!(unit.format(0.0, 0.1), "0:00.0");
+        assert_eq!(unit.format(3.5, 0.1), "0:03.5");
+        assert_eq!(unit.format(19.5, 0.1), "1:03.5");
+        assert_eq!(unit.format(15.96, 0.1), "1:00.0");
+        assert_eq!(unit.format(-3.5, 0.1), "-0:03.5");
+        assert_eq!(unit.format(-0.01, 0.1), "0:00.0");
+        assert_eq!(unit.format(16_000.0, 0.1), "inf");
+        assert_eq!(unit.format(-16_000.0, 0.1), "-inf");
+        assert_eq!(unit.format(f32::NAN, 0.1), "NaN");
+    }
+
+    #[test]
+    fn weight_rows_are_16_columns() {
+        assert_eq!(
+            weight_row("WEIGHT", 12.5, WeightUnit::Grams, 0.1),
+            "WEIGHT    12.5 g"
+        );
+        assert_eq!(
+            weight_row("MAX", 12.5 * GRAMS_PER_OUNCE, WeightUnit::Ounces, 0.01),
+            "MAX     12.50 oz"
+        );
+        assert_eq!(
+            weight_row(
+                "LEFT",
+                19.5 * GRAMS_PER_OUNCE,
+                WeightUnit::PoundsOunces,
+                0.1
+            ),
+            "LEFT      1:03.5"
+        );
+    }
+
+    #[test]
+    fn units_are_cycled() {
+        let mut unit = WeightUnit::Grams;
+        for i in 1..=WeightUnit::ALL.len() {
+            unit = unit.next();
+            assert_eq!(WeightUnit::from_index(unit.index()), Some(unit));
+            assert_eq!(unit.index() as usize, i % WeightUnit::ALL.len());
+        }
+        assert_eq!(unit, WeightUnit::Grams);
+    }
+}
@@ -0,0 +1,314 @@
+use alloc::{boxed::Box, rc::Rc, vec::Vec};
+use core::cell::RefCell;
+
+use crate::{
+    common::{AppContext, AppMessage, AppState},
+    terminal::Terminal,
+};
+use stuff::{
+    mq::MessageProcessingStatus,
+    run_loop::{Task, TaskStatus},
+};
+
+/// A screen.
+pub trait View {
+    /// Handle a message. Only the top view receives messages.
+    ///
+    /// Input events are consumed by the view stack whether or not they are
+    /// relevant to the view, other ignored messages are left in the queue.
+    fn handle_message(&mut self, m: &AppMessage, cx: &mut ViewContext) -> MessageProcessingStatus;
+
+    /// Render the view. Only the top view is rendered.
+    fn render(&mut self, terminal: &mut dyn Terminal, state: &AppState) -> core::fmt::Result;
+
+    /// Called on every run of the top view after the messages are handled,
+    /// e.g. to follow the state or to navigate because of it.
+    fn update(&mut self, _cx: &mut ViewContext) {}
+
+    /// Called when the view becomes the top one, i.e. when it's pushed
+    /// or uncovered. The screen is cleared at this point.
+    fn appear(&mut self) {}
+}
+
+pub struct ViewContext<'a> {
+    pub state: &'a AppState,
+    push: &'a mut dyn FnMut(AppMessage),
+    navigation: Option<Navigation>,
+}
+
+enum Navigation {
+    Push(Box<dyn View>),
+    Pop,
+    Replace(Box<dyn View>),
+}
+
+impl<'a> ViewContext<'a> {
+    /// Push a message to the message queue.
+    pub fn send(&mut self, m: AppMessage) {
+        (self.push)(m);
+    }
+
+    /// Show a view on top of the current one.
+    pub fn push_view(&mut self, view: Box<dyn View>) {
+        self.navigation = Some(Navigation::Push(view));
+    }
+
+    /// Close the current view.
+    pub fn pop_view(&mut self) {
+        self.navigation = Some(Navigation::Pop);
+    }
+
+    /// Close the current view and show another one in its place.
+    pub fn replace_view(&mut self, view: Box<dyn View>) {
+        self.navigation = Some(Navigation::Replace(view));
+    }
+}
+
+/// Views on top of each other, cf. `ui/view_stack_task.hpp`.
+///
+/// The task is done when the last view is popped.
+pub struct ViewStack {
+    terminal: Rc<RefCell<dyn Terminal>>,
+    views: Vec<Box<dyn View>>,
+    is_top_shown: bool,
+}
+
+impl ViewStack {
+    pub fn new(terminal: Rc<RefCell<dyn Terminal>>) -> Self {
+        Self {
+            terminal,
+            views: Vec::new(),
+            is_top_shown: false,
+        }
+    }
+
+    pub fn push(&mut self, view: Box<dyn View>) {
+        self.views.push(view);
+        self.is_top_shown = false;
+    }
+
+    pub fn len(&self) -> usize {
+        self.views.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.views.is_empty()
+    }
+
+    fn navigate(&mut self, navigation: Navigation) {
+        match navigation {
+            Navigation::Push(view) => self.views.push(view),
+            Navigation::Pop => _ = self.views.pop(),
+            Navigation::Replace(view) => {
+                self.views.pop();
+                self.views.push(view);
+            }
+        }
+        self.is_top_shown = false;
+    }
+}
+
+impl Task<AppContext> for ViewStack {
+    fn run(&mut self, cx: &mut AppContext) -> TaskStatus {
+        let Some(view) = self.views.last_mut() else {
+            return TaskStatus::Done;
+        };
+        let mut navigation = None;
+        cx.mq.process(|m, push| {
+            if navigation.is_some() {
+                // Left for the next top view
+                return MessageProcessingStatus::Ignored;
+            }
+            let mut view_cx = ViewContext {
+                state: &cx.state,
+                push,
+                navigation: None,
+            };
+            let status = view.handle_message(m, &mut view_cx);
+            navigation = view_cx.navigation;
+            match m {
+                AppMessage::InputEvent(_) => MessageProcessingStatus::Processed,
+                _ => status,
+            }
+        });
+        if navigation.is_none() {
+            let mut push = |m| cx.mq.push(m);
+            let mut view_cx = ViewContext {
+                state: &cx.state,
+                push: &mut push,
+                navigation: None,
+            };
+            view.update(&mut view_cx);
+            navigation = view_cx.navigation;
+        }
+        if let Some(navigation) = navigation {
+            self.navigate(navigation);
+        }
+
+        let Some(view) = self.views.last_mut() else {
+            return TaskStatus::Done;
+        };
+        let mut terminal = self.terminal.borrow_mut();
+        if !self.is_top_shown {
+            terminal.clear().unwrap();
+            view.appear();
+            self.is_top_shown = true;
+        }
+        view.render(&mut *terminal, &cx.state).unwrap();
+        TaskStatus::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{string::String, vec};
+
+    use super::*;
+    use crate::{
+        button::ButtonEvent,
+        common::InputEvent,
+        testing::{input, FakeTerminal},
+    };
+
+    type Log = Rc<RefCell<Vec<String>>>;
+
+    /// Logs the received input events and opens another `TestView` on
+    /// a long press of button A, closes itself on a long press of button B.
+    /// Replaces itself with another one when the weight is negative.
+    struct TestView {
+        name: &'static str,
+        log: Log,
+    }
+
+    impl TestView {
+        fn new(name: &'static str, log: &Log) -> Box<Self> {
+            Box::new(Self {
+                name,
+                log: log.clone(),
+            })
+        }
+    }
+
+    impl View for TestView {
+        fn handle_message(
+            &mut self,
+            m: &AppMessage,
+            cx: &mut ViewContext,
+        ) -> MessageProcessingStatus {
+            match m {
+                AppMessage::InputEvent(e) => {
+                    self.log
+                        .borrow_mut()
+                        .push(alloc::format!("{}: {e:?}", self.name));
+                    match e {
+                        InputEvent::ButtonA(ButtonEvent::LongPress) => {
+                            cx.push_view(TestView::new("inner", &self.log))
+                        }
+                        InputEvent::ButtonB(ButtonEvent::LongPress) => cx.pop_view(),
+                        _ => {}
+                    }
+                    MessageProcessingStatus::Processed
+                }
+                _ => MessageProcessingStatus::Ignored,
+            }
+        }
+
+        fn render(&mut self, terminal: &mut dyn Terminal, _: &AppState) -> core::fmt::Result {
+            terminal.set_position(0, 0)?;
+            terminal.write_str(self.name)
+        }
+
+        fn update(&mut self, cx: &mut ViewContext) {
+            if cx.state.weight < 0.0 && self.name != "replacement" {
+                cx.replace_view(TestView::new("replacement", &self.log));
+            }
+        }
+    }
+
+    fn stack() -> (ViewStack, Rc<RefCell<FakeTerminal>>, Log) {
+        let terminal = Rc::new(RefCell::new(FakeTerminal::default()));
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut stack = ViewStack::new(terminal.clone());
+        stack.push(TestView::new("outer", &log));
+        (stack, terminal, log)
+    }
+
+    #[test]
+    fn only_the_top_view_receives_input_and_renders() {
+        let mut cx = AppContext::default();
+        let (mut stack, terminal, log) = stack();
+
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::LongPress));
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::Press));
+        stack.run(&mut cx);
+        stack.run(&mut cx);
+        assert_eq!(stack.len(), 2);
+        // The second event wasn't processed by the outer view
+        assert_eq!(
+            *log.borrow(),
+            ["outer: ButtonA(LongPress)", "inner: ButtonA(Press)"]
+        );
+        assert_eq!(terminal.borrow().line(0), "inner");
+
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::LongPress));
+        stack.run(&mut cx);
+        assert_eq!(stack.len(), 1);
+        assert_eq!(terminal.borrow().line(0), "outer");
+    }
+
+    #[test]
+    fn messages_after_navigation_go_to_the_new_top_view() {
+        let mut cx = AppContext::default();
+        let (mut stack, _, log) = stack();
+
+        input(&mut cx, InputEvent::ButtonA(ButtonEvent::LongPress));
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::Press));
+        stack.run(&mut cx);
+        // The second event is left in the queue until the next run
+        assert_eq!(*log.borrow(), ["outer: ButtonA(LongPress)"]);
+        stack.run(&mut cx);
+        assert_eq!(
+            *log.borrow(),
+            ["outer: ButtonA(LongPress)", "inner: ButtonB(Press)"]
+        );
+    }
+
+    #[test]
+    fn ignored_messages_are_left_in_the_queue() {
+        let mut cx = AppContext::default();
+        let (mut stack, _, _) = stack();
+
+        cx.mq.push(AppMessage::Tare);
+        stack.run(&mut cx);
+        let mut ms = vec![];
+        cx.mq.process(|m, _| {
+            ms.push(m.clone());
+            MessageProcessingStatus::Processed
+        });
+        assert_eq!(ms, [AppMessage::Tare]);
+    }
+
+    #[test]
+    fn top_view_navigates_on_update() {
+        let mut cx = AppContext::default();
+        let (mut stack, terminal, _) = stack();
+
+        stack.run(&mut cx);
+        assert_eq!(terminal.borrow().line(0), "outer");
+        cx.state.weight = -1.0;
+        stack.run(&mut cx);
+        assert_eq!(stack.len(), 1);
+        assert_eq!(terminal.borrow().line(0), "replacement");
+    }
+
+    #[test]
+    fn stack_is_done_when_the_last_view_is_popped() {
+        let mut cx = AppContext::default();
+        let (mut stack, _, _) = stack();
+
+        assert!(matches!(stack.run(&mut cx), TaskStatus::Pending));
+        input(&mut cx, InputEvent::ButtonB(ButtonEvent::LongPress));
+        assert!(matches!(stack.run(&mut cx), TaskStatus::Done));
+        assert!(stack.is_empty());
+    }
+}
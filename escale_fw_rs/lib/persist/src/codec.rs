@@ -0,0 +1,123 @@
+//! Little-endian encoding of primitive values.
+
+/// Writes values one after another.
+pub struct Writer<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl<'a> Writer<'a> {
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    /// The number of bytes written so far.
+    pub fn len(&self) -> usize {
+        self.pos
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos == 0
+    }
+
+    pub fn bytes(&mut self, data: &[u8]) -> Result<(), Error> {
+        let end = self.pos + data.len();
+        self.buf
+            .get_mut(self.pos..end)
+            .ok_or(Error::BufferTooSmall)?
+            .copy_from_slice(data);
+        self.pos = end;
+        Ok(())
+    }
+
+    pub fn u8(&mut self, value: u8) -> Result<(), Error> {
+        self.bytes(&[value])
+    }
+
+    pub fn u16(&mut self, value: u16) -> Result<(), Error> {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    pub fn u32(&mut self, value: u32) -> Result<(), Error> {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    pub fn f32(&mut self, value: f32) -> Result<(), Error> {
+        self.bytes(&value.to_le_bytes())
+    }
+}
+
+/// Reads values one after another.
+///
+/// Reading past the end yields `None`, so a reader of the newer format can
+/// tell which fields are missing in the older one.
+pub struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    pub fn bytes<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let data = self.buf.get(self.pos..self.pos + N)?;
+        self.pos += N;
+        data.try_into().ok()
+    }
+
+    pub fn u8(&mut self) -> Option<u8> {
+        self.bytes::<1>().map(|[b]| b)
+    }
+
+    pub fn u16(&mut self) -> Option<u16> {
+        self.bytes().map(u16::from_le_bytes)
+    }
+
+    pub fn u32(&mut self) -> Option<u32> {
+        self.bytes().map(u32::from_le_bytes)
+    }
+
+    pub fn f32(&mut self) -> Option<f32> {
+        self.bytes().map(f32::from_le_bytes)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    BufferTooSmall,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_round_trip() {
+        let mut buf = [0u8; 11];
+        let mut w = Writer::new(&mut buf);
+        w.u8(7).unwrap();
+        w.u16(0x1234).unwrap();
+        w.u32(0xdead_beef).unwrap();
+        w.f32(-1.5).unwrap();
+        assert_eq!(w.len(), 11);
+        assert_eq!(buf[1..3], [0x34, 0x12]);
+
+        let mut r = Reader::new(&buf);
+        assert_eq!(r.u8(), Some(7));
+        assert_eq!(r.u16(), Some(0x1234));
+        assert_eq!(r.u32(), Some(0xdead_beef));
+        assert_eq!(r.f32(), Some(-1.5));
+        assert_eq!(r.u8(), None);
+    }
+
+    #[test]
+    fn writing_past_the_end_fails() {
+        let mut buf = [0u8; 3];
+        let mut w = Writer::new(&mut buf);
+        w.u16(1).unwrap();
+        assert_eq!(w.u16(2), Err(Error::BufferTooSmall));
+        assert_eq!(w.len(), 2);
+    }
+}
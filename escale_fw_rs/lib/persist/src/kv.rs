@@ -0,0 +1,592 @@
+//! A log-structured key-value store.
+//!
+//! Updating a value appends an entry to the current sector instead of
+//! erasing anything. When the current sector is full, the next erased one is
+//! opened going around the storage, so all the sectors wear evenly.
+//! One sector is always kept erased: when it's the last one, it's opened
+//! and the live entries of the oldest sector are copied into it, then
+//! the oldest sector is erased. Its magic is cleared first, so a sector
+//! left partly erased by a power cut isn't taken for the source of an
+//! interrupted compaction.
+//!
+//! Every sector starts with a header:
+//!
+//! | Offset | Size | Field                                  |
+//! |--------|------|----------------------------------------|
+//! | 0      | 4    | Sequence number, larger in newer ones  |
+//! | 4      | 4    | Magic                                  |
+//!
+//! followed by the entries, each aligned to 4 bytes:
+//!
+//! | Offset | Size | Field                                      |
+//! |--------|------|--------------------------------------------|
+//! | 0      | 2    | Key                                        |
+//! | 2      | 2    | Value length, the top bit marks a removal  |
+//! | 4      | 4    | CRC-32 of the key, length and value        |
+//! | 8      | *    | Value                                      |
+//!
+//! All the fields are little-endian. An entry torn by a power cut fails
+//! the checksum and is skipped, so the previous value of the key stays.
+
+use crate::{
+    crc32::Crc32,
+    storage::{Storage, ERASED},
+};
+
+pub type Key = u16;
+
+const SECTOR_MAGIC: u32 = u32::from_le_bytes(*b"ESKV");
+const SECTOR_MAGIC_OFFSET: usize = 4;
+const SECTOR_HEADER_SIZE: usize = 8;
+const ENTRY_HEADER_SIZE: usize = 8;
+const ENTRY_ALIGN: usize = 4;
+/// The length bit of an entry that removes the key.
+const TOMBSTONE: u16 = 0x8000;
+/// The key of an erased entry header.
+const ERASED_KEY: Key = Key::MAX;
+/// The size of the chunks the values are copied and checksummed in.
+const CHUNK_SIZE: usize = 32;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error<E> {
+    Storage(E),
+    /// The store needs at least two sectors.
+    TooFewSectors,
+    InvalidKey,
+    /// The value doesn't fit in a sector.
+    ValueTooLarge,
+    BufferTooSmall,
+    /// There is no space left even after compaction.
+    Full,
+}
+
+pub struct Store<S> {
+    storage: S,
+    head: Head,
+}
+
+/// The sector the entries are appended to.
+#[derive(Clone, Copy)]
+struct Head {
+    sector: usize,
+    sequence: u32,
+    /// The offset of the next entry.
+    offset: usize,
+}
+
+struct Entry {
+    key: Key,
+    /// The offset of the entry in the storage.
+    addr: usize,
+    value_len: usize,
+    is_removal: bool,
+    is_valid: bool,
+    /// The offset of the next entry in the sector.
+    next: usize,
+}
+
+impl<S: Storage> Store<S> {
+    /// Open the store, erasing the sectors damaged by a power cut and
+    /// finishing an interrupted compaction.
+    pub fn mount(mut storage: S) -> Result<Self, Error<S::Error>> {
+        let sector_count = storage.sector_count();
+        if sector_count < 2 {
+            return Err(Error::TooFewSectors);
+        }
+        // The newest sector and the one before it
+        let mut newest: [Option<(usize, u32)>; 2] = [None; 2];
+        let mut erased_count = 0;
+        for sector in 0..sector_count {
+            if let Some(sequence) = sequence(&storage, sector)? {
+                if newest[0].is_none_or(|(_, newest)| sequence > newest) {
+                    newest = [Some((sector, sequence)), newest[0]];
+                } else if newest[1].is_none_or(|(_, previous)| sequence > previous) {
+                    newest[1] = Some((sector, sequence));
+                }
+            } else {
+                if !is_erased(&storage, sector)? {
+                    storage.erase(sector).map_err(Error::Storage)?;
+                }
+                erased_count += 1;
+            }
+        }
+        // A compaction was interrupted before the oldest sector was
+        // invalidated. The newest sector has nothing but the copies of the
+        // entries of the oldest one, so it's started over.
+        let is_compacting = erased_count == 0;
+        if is_compacting {
+            if let Some((sector, _)) = newest[0] {
+                storage.erase(sector).map_err(Error::Storage)?;
+                newest = [newest[1], None];
+            }
+        }
+
+        // Without any sector in use, the first one opened is 0
+        let mut store = Self {
+            head: Head {
+                sector: sector_count - 1,
+                sequence: 0,
+                offset: storage.sector_size(),
+            },
+            storage,
+        };
+        if let Some((sector, sequence)) = newest[0] {
+            store.head = Head {
+                sector,
+                sequence,
+                offset: store.end_of(sector)?,
+            };
+        }
+        if is_compacting {
+            store.open_next()?;
+            store.compact()?;
+        }
+        Ok(store)
+    }
+
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    pub fn into_storage(self) -> S {
+        self.storage
+    }
+
+    /// Read the value of the key into `buf`.
+    ///
+    /// Returns the length of the value.
+    pub fn get(&self, key: Key, buf: &mut [u8]) -> Result<Option<usize>, Error<S::Error>> {
+        match self.find(key)? {
+            Some(entry) if !entry.is_removal => {
+                let buf = buf
+                    .get_mut(..entry.value_len)
+                    .ok_or(Error::BufferTooSmall)?;
+                self.storage
+                    .read(entry.addr + ENTRY_HEADER_SIZE, buf)
+                    .map_err(Error::Storage)?;
+                Ok(Some(entry.value_len))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    pub fn set(&mut self, key: Key, value: &[u8]) -> Result<(), Error<S::Error>> {
+        let len = u16::try_from(value.len())
+            .ok()
+            .filter(|&len| len & TOMBSTONE == 0)
+            .ok_or(Error::ValueTooLarge)?;
+        self.append(key, len, value)
+    }
+
+    pub fn remove(&mut self, key: Key) -> Result<(), Error<S::Error>> {
+        match self.find(key)? {
+            Some(entry) if !entry.is_removal => self.append(key, TOMBSTONE, &[]),
+            _ => Ok(()),
+        }
+    }
+
+    fn append(&mut self, key: Key, len: u16, value: &[u8]) -> Result<(), Error<S::Error>> {
+        if key == ERASED_KEY {
+            return Err(Error::InvalidKey);
+        }
+        let size = entry_size(value.len());
+        let sector_size = self.storage.sector_size();
+        if SECTOR_HEADER_SIZE + size > sector_size {
+            return Err(Error::ValueTooLarge);
+        }
+        // Every sector is compacted at most once before giving up
+        for _ in 0..=self.storage.sector_count() {
+            if self.head.offset + size <= sector_size {
+                let mut crc = Crc32::default();
+                crc.update(&key.to_le_bytes());
+                crc.update(&len.to_le_bytes());
+                crc.update(value);
+                let mut header = [0; ENTRY_HEADER_SIZE];
+                header[0..2].copy_from_slice(&key.to_le_bytes());
+                header[2..4].copy_from_slice(&len.to_le_bytes());
+                header[4..8].copy_from_slice(&crc.finish().to_le_bytes());
+
+                let addr = self.head.sector * sector_size + self.head.offset;
+                self.head.offset += size;
+                self.program(addr, &header)?;
+                self.program(addr + ENTRY_HEADER_SIZE, value)?;
+                return Ok(());
+            }
+            let is_last_erased = self.erased_count()? == 1;
+            self.open_next()?;
+            if is_last_erased {
+                self.compact()?;
+            }
+        }
+        Err(Error::Full)
+    }
+
+    /// Find the newest valid entry of the key.
+    fn find(&self, key: Key) -> Result<Option<Entry>, Error<S::Error>> {
+        let mut newest: Option<(u32, Entry)> = None;
+        for sector in 0..self.storage.sector_count() {
+            let Some(sequence) = sequence(&self.storage, sector)? else {
+                continue;
+            };
+            let mut offset = SECTOR_HEADER_SIZE;
+            while let Some(entry) = self.entry_at(sector, offset)? {
+                offset = entry.next;
+                if entry.is_valid
+                    && entry.key == key
+                    && newest
+                        .as_ref()
+                        .is_none_or(|(newest, _)| sequence >= *newest)
+                {
+                    newest = Some((sequence, entry));
+                }
+            }
+        }
+        Ok(newest.map(|(_, entry)| entry))
+    }
+
+    /// Read the entry at the offset in the sector, if any.
+    fn entry_at(&self, sector: usize, offset: usize) -> Result<Option<Entry>, Error<S::Error>> {
+        let sector_size = self.storage.sector_size();
+        if offset + ENTRY_HEADER_SIZE > sector_size {
+            return Ok(None);
+        }
+        let addr = sector * sector_size + offset;
+        let mut header = [0; ENTRY_HEADER_SIZE];
+        self.storage
+            .read(addr, &mut header)
+            .map_err(Error::Storage)?;
+        if header == [ERASED; ENTRY_HEADER_SIZE] {
+            return Ok(None);
+        }
+        let key = Key::from_le_bytes([header[0], header[1]]);
+        let len = u16::from_le_bytes([header[2], header[3]]);
+        let crc = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        let value_len = (len & !TOMBSTONE) as usize;
+        let next = offset + entry_size(value_len);
+        if next > sector_size {
+            // The header is torn, so the rest of the sector is unusable
+            return Ok(Some(Entry {
+                key,
+                addr,
+                value_len: 0,
+                is_removal: false,
+                is_valid: false,
+                next: sector_size,
+            }));
+        }
+
+        let mut actual_crc = Crc32::default();
+        actual_crc.update(&header[0..4]);
+        let mut chunk = [0; CHUNK_SIZE];
+        let value_addr = addr + ENTRY_HEADER_SIZE;
+        for start in (0..value_len).step_by(CHUNK_SIZE) {
+            let chunk = &mut chunk[..CHUNK_SIZE.min(value_len - start)];
+            self.storage
+                .read(value_addr + start, chunk)
+                .map_err(Error::Storage)?;
+            actual_crc.update(chunk);
+        }
+        Ok(Some(Entry {
+            key,
+            addr,
+            value_len,
+            is_removal: len & TOMBSTONE != 0,
+            is_valid: actual_crc.finish() == crc,
+            next,
+        }))
+    }
+
+    /// The offset following the last entry of the sector.
+    fn end_of(&self, sector: usize) -> Result<usize, Error<S::Error>> {
+        let mut offset = SECTOR_HEADER_SIZE;
+        while let Some(entry) = self.entry_at(sector, offset)? {
+            offset = entry.next;
+        }
+        Ok(offset)
+    }
+
+    fn erased_count(&self) -> Result<usize, Error<S::Error>> {
+        let mut count = 0;
+        for sector in 0..self.storage.sector_count() {
+            if sequence(&self.storage, sector)?.is_none() {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Start appending to the erased sector following the head.
+    fn open_next(&mut self) -> Result<(), Error<S::Error>> {
+        let sector_count = self.storage.sector_count();
+        for i in 1..=sector_count {
+            let sector = (self.head.sector + i) % sector_count;
+            if sequence(&self.storage, sector)?.is_none() {
+                let sequence = self.head.sequence.wrapping_add(1);
+                let mut header = [0; SECTOR_HEADER_SIZE];
+                header[0..4].copy_from_slice(&sequence.to_le_bytes());
+                header[4..8].copy_from_slice(&SECTOR_MAGIC.to_le_bytes());
+                self.head = Head {
+                    sector,
+                    sequence,
+                    offset: SECTOR_HEADER_SIZE,
+                };
+                return self.program(sector * self.storage.sector_size(), &header);
+            }
+        }
+        Err(Error::Full)
+    }
+
+    /// Copy the live entries of the oldest sector to the head and erase it.
+    fn compact(&mut self) -> Result<(), Error<S::Error>> {
+        let mut oldest: Option<(usize, u32)> = None;
+        for sector in 0..self.storage.sector_count() {
+            if sector == self.head.sector {
+                continue;
+            }
+            if let Some(sequence) = sequence(&self.storage, sector)? {
+                if oldest.is_none_or(|(_, oldest)| sequence < oldest) {
+                    oldest = Some((sector, sequence));
+                }
+            }
+        }
+        let Some((oldest, _)) = oldest else {
+            return Ok(());
+        };
+
+        let sector_size = self.storage.sector_size();
+        let mut offset = SECTOR_HEADER_SIZE;
+        while let Some(entry) = self.entry_at(oldest, offset)? {
+            offset = entry.next;
+            // Nothing older than a removal is left, so it's dropped
+            if !entry.is_valid || entry.is_removal {
+                continue;
+            }
+            if self
+                .find(entry.key)?
+                .is_none_or(|newest| newest.addr != entry.addr)
+            {
+                continue;
+            }
+            let size = entry_size(entry.value_len);
+            if self.head.offset + size > sector_size {
+                return Err(Error::Full);
+            }
+            let addr = self.head.sector * sector_size + self.head.offset;
+            self.head.offset += size;
+            let mut chunk = [0; CHUNK_SIZE];
+            let len = ENTRY_HEADER_SIZE + entry.value_len;
+            for start in (0..len).step_by(CHUNK_SIZE) {
+                let chunk = &mut chunk[..CHUNK_SIZE.min(len - start)];
+                self.storage
+                    .read(entry.addr + start, chunk)
+                    .map_err(Error::Storage)?;
+                self.program(addr + start, chunk)?;
+            }
+        }
+        self.program(oldest * sector_size + SECTOR_MAGIC_OFFSET, &[0; 4])?;
+        self.storage.erase(oldest).map_err(Error::Storage)
+    }
+
+    fn program(&mut self, addr: usize, data: &[u8]) -> Result<(), Error<S::Error>> {
+        self.storage.program(addr, data).map_err(Error::Storage)
+    }
+}
+
+/// The sequence number of the sector in use, or `None` if it's erased.
+fn sequence<S: Storage>(storage: &S, sector: usize) -> Result<Option<u32>, Error<S::Error>> {
+    let mut header = [0; SECTOR_HEADER_SIZE];
+    storage
+        .read(sector * storage.sector_size(), &mut header)
+        .map_err(Error::Storage)?;
+    let sequence = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+    let magic = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+    Ok((magic == SECTOR_MAGIC).then_some(sequence))
+}
+
+fn is_erased<S: Storage>(storage: &S, sector: usize) -> Result<bool, Error<S::Error>> {
+    let sector_size = storage.sector_size();
+    let mut chunk = [0; CHUNK_SIZE];
+    for start in (0..sector_size).step_by(CHUNK_SIZE) {
+        let chunk = &mut chunk[..CHUNK_SIZE.min(sector_size - start)];
+        storage
+            .read(sector * sector_size + start, chunk)
+            .map_err(Error::Storage)?;
+        if chunk.iter().any(|&b| b != ERASED) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn entry_size(value_len: usize) -> usize {
+    (ENTRY_HEADER_SIZE + value_len).next_multiple_of(ENTRY_ALIGN)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{RamError, RamStorage};
+
+    const SECTOR_SIZE: usize = 128;
+    const SECTOR_COUNT: usize = 4;
+
+    type TestStorage = RamStorage<SECTOR_SIZE, SECTOR_COUNT>;
+    type TestStore = Store<TestStorage>;
+
+    fn get(store: &TestStore, key: Key) -> Option<Vec<u8>> {
+        let mut buf = [0; SECTOR_SIZE];
+        let len = store.get(key, &mut buf).unwrap()?;
+        Some(buf[..len].to_vec())
+    }
+
+    #[test]
+    fn values_are_set_and_removed() {
+        let mut store = Store::mount(TestStorage::default()).unwrap();
+        assert_eq!(get(&store, 1), None);
+        store.set(1, b"one").unwrap();
+        store.set(2, b"two").unwrap();
+        store.set(1, b"uno").unwrap();
+        assert_eq!(get(&store, 1).as_deref(), Some(&b"uno"[..]));
+        assert_eq!(get(&store, 2).as_deref(), Some(&b"two"[..]));
+        store.remove(2).unwrap();
+        assert_eq!(get(&store, 2), None);
+        store.set(3, b"").unwrap();
+        assert_eq!(get(&store, 3).as_deref(), Some(&b""[..]));
+
+        let mut buf = [0; 2];
+        assert_eq!(store.get(1, &mut buf), Err(Error::BufferTooSmall));
+        assert_eq!(store.set(ERASED_KEY, b""), Err(Error::InvalidKey));
+        assert_eq!(store.set(4, &[0; SECTOR_SIZE]), Err(Error::ValueTooLarge));
+    }
+
+    #[test]
+    fn values_survive_remount() {
+        let mut store = Store::mount(TestStorage::default()).unwrap();
+        store.set(1, b"one").unwrap();
+        store.set(2, b"two").unwrap();
+        store.remove(1).unwrap();
+        let mut store = Store::mount(store.into_storage()).unwrap();
+        assert_eq!(get(&store, 1), None);
+        assert_eq!(get(&store, 2).as_deref(), Some(&b"two"[..]));
+        store.set(1, b"uno").unwrap();
+        assert_eq!(get(&store, 1).as_deref(), Some(&b"uno"[..]));
+    }
+
+    #[test]
+    fn compaction_keeps_live_values_and_levels_wear() {
+        let mut store = Store::mount(TestStorage::default()).unwrap();
+        store.set(100, b"constant").unwrap();
+        for i in 0..1_000u32 {
+            store.set((i % 3) as Key, &i.to_le_bytes()).unwrap();
+        }
+        assert_eq!(get(&store, 100).as_deref(), Some(&b"constant"[..]));
+        for key in 0..3 {
+            let i = 999 - (999 - key as u32) % 3;
+            assert_eq!(get(&store, key), Some(i.to_le_bytes().to_vec()));
+        }
+
+        let counts = (0..SECTOR_COUNT).map(|sector| store.storage().erase_count(sector));
+        let (min, max) = (counts.clone().min().unwrap(), counts.max().unwrap());
+        assert!(min > 0 && max - min <= 1, "{min}..{max}");
+    }
+
+    #[test]
+    fn full_store_is_reported() {
+        let mut store = Store::mount(TestStorage::default()).unwrap();
+        let value = [0; 52];
+        // Two entries fit in a sector, one sector is kept erased
+        let capacity = (SECTOR_COUNT as Key - 1) * 2;
+        for key in 0..capacity {
+            store.set(key, &value).unwrap();
+        }
+        assert_eq!(store.set(capacity, &value), Err(Error::Full));
+        for key in 0..capacity {
+            assert_eq!(get(&store, key), Some(value.to_vec()));
+        }
+        assert_eq!(get(&store, capacity), None);
+    }
+
+    #[test]
+    fn power_cut_keeps_the_previous_value() {
+        // Cut the power at every step of every update, including the ones
+        // opening a sector and compacting
+        for update in 1..40u32 {
+            for cut in 0.. {
+                let mut store = Store::mount(TestStorage::default()).unwrap();
+                store.set(100, b"constant").unwrap();
+                for i in 0..update {
+                    store.set(1, &i.to_le_bytes()).unwrap();
+                }
+                store.storage.cut_power_after(Some(cut));
+                let is_done = match store.set(1, &update.to_le_bytes()) {
+                    Ok(()) => true,
+                    Err(Error::Storage(RamError::PowerCut)) => false,
+                    Err(e) => panic!("{e:?}"),
+                };
+
+                let mut storage = store.into_storage();
+                storage.cut_power_after(None);
+                let mut store = Store::mount(storage).unwrap();
+                let value = get(&store, 1).unwrap();
+                if is_done {
+                    assert_eq!(value, update.to_le_bytes());
+                } else {
+                    assert!(
+                        value == update.to_le_bytes() || value == (update - 1).to_le_bytes(),
+                        "update {update}, cut at {cut}"
+                    );
+                }
+                assert_eq!(get(&store, 100).as_deref(), Some(&b"constant"[..]));
+
+                // The store keeps working
+                store.set(1, b"next").unwrap();
+                assert_eq!(get(&store, 1).as_deref(), Some(&b"next"[..]));
+                if is_done {
+                    break;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn power_cut_while_erasing_the_compacted_sector_keeps_its_values() {
+        // Cut the power at every step up to the end of the first compaction,
+        // so also while the compacted sector is erased, which leaves it
+        // partly erased
+        for cut in 0.. {
+            let mut store = Store::mount(TestStorage::default()).unwrap();
+            for i in 0..8u32 {
+                store.set(1, &i.to_le_bytes()).unwrap();
+            }
+            // In the erased half of the first sector
+            store.set(100, b"constant").unwrap();
+            store.storage.cut_power_after(Some(cut));
+            let mut i = 8u32;
+            let is_done = loop {
+                match store.set(1, &i.to_le_bytes()) {
+                    Ok(()) if store.storage().erase_count(0) > 0 => break true,
+                    Ok(()) => i += 1,
+                    Err(Error::Storage(RamError::PowerCut)) => break false,
+                    Err(e) => panic!("{e:?}"),
+                }
+            };
+
+            let mut storage = store.into_storage();
+            storage.cut_power_after(None);
+            let store = Store::mount(storage).unwrap();
+            assert_eq!(
+                get(&store, 100).as_deref(),
+                Some(&b"constant"[..]),
+                "cut at {cut}"
+            );
+            let value = get(&store, 1).unwrap();
+            assert!(
+                value == i.to_le_bytes() || value == (i - 1).to_le_bytes(),
+                "cut at {cut}"
+            );
+            if is_done {
+                break;
+            }
+        }
+    }
+}
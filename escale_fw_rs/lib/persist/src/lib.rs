@@ -0,0 +1,6 @@
+#![cfg_attr(not(test), no_std)]
+
+pub mod codec;
+pub mod crc32;
+pub mod kv;
+pub mod storage;
@@ -0,0 +1,177 @@
+//! Erasable storage, such as NOR flash.
+
+/// The value of the erased storage.
+pub const ERASED: u8 = 0xff;
+
+/// Storage that is erased in sectors and programmed in bytes.
+///
+/// Like NOR flash, programming can only turn the erased bits into zeros,
+/// so a byte must be erased before it can be programmed a different value.
+/// The offsets are relative to the beginning of the storage.
+pub trait Storage {
+    type Error;
+
+    /// The size of the smallest erasable unit.
+    fn sector_size(&self) -> usize;
+
+    fn sector_count(&self) -> usize;
+
+    fn read(&self, offset: usize, buf: &mut [u8]) -> Result<(), Self::Error>;
+
+    /// Set all the bytes of the sector to [`ERASED`].
+    fn erase(&mut self, sector: usize) -> Result<(), Self::Error>;
+
+    fn program(&mut self, offset: usize, data: &[u8]) -> Result<(), Self::Error>;
+}
+
+/// Storage in RAM for host tests.
+///
+/// Erasing a sector and programming a byte are counted as steps,
+/// so the power can be cut at any of them (see [`RamStorage::cut_power_after`]).
+/// An erase cut by the power leaves the sector partly erased, here its
+/// second half.
+pub struct RamStorage<const SECTOR_SIZE: usize, const SECTOR_COUNT: usize> {
+    sectors: [[u8; SECTOR_SIZE]; SECTOR_COUNT],
+    erase_counts: [u32; SECTOR_COUNT],
+    budget: Option<usize>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RamError {
+    OutOfBounds,
+    PowerCut,
+}
+
+impl<const SECTOR_SIZE: usize, const SECTOR_COUNT: usize> Default
+    for RamStorage<SECTOR_SIZE, SECTOR_COUNT>
+{
+    fn default() -> Self {
+        Self {
+            sectors: [[ERASED; SECTOR_SIZE]; SECTOR_COUNT],
+            erase_counts: [0; SECTOR_COUNT],
+            budget: None,
+        }
+    }
+}
+
+impl<const SECTOR_SIZE: usize, const SECTOR_COUNT: usize> RamStorage<SECTOR_SIZE, SECTOR_COUNT> {
+    /// Fail every operation after the given number of steps,
+    /// or never if `None`.
+    pub fn cut_power_after(&mut self, steps: Option<usize>) {
+        self.budget = steps;
+    }
+
+    pub fn erase_count(&self, sector: usize) -> u32 {
+        self.erase_counts[sector]
+    }
+
+    fn step(&mut self) -> Result<(), RamError> {
+        match self.budget.as_mut() {
+            Some(0) => Err(RamError::PowerCut),
+            Some(n) => {
+                *n -= 1;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn check_bounds(offset: usize, len: usize) -> Result<(), RamError> {
+        if offset + len <= SECTOR_SIZE * SECTOR_COUNT {
+            Ok(())
+        } else {
+            Err(RamError::OutOfBounds)
+        }
+    }
+}
+
+impl<const SECTOR_SIZE: usize, const SECTOR_COUNT: usize> Storage
+    for RamStorage<SECTOR_SIZE, SECTOR_COUNT>
+{
+    type Error = RamError;
+
+    fn sector_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    fn sector_count(&self) -> usize {
+        SECTOR_COUNT
+    }
+
+    fn read(&self, offset: usize, buf: &mut [u8]) -> Result<(), Self::Error> {
+        Self::check_bounds(offset, buf.len())?;
+        for (i, b) in buf.iter_mut().enumerate() {
+            let offset = offset + i;
+            *b = self.sectors[offset / SECTOR_SIZE][offset % SECTOR_SIZE];
+        }
+        Ok(())
+    }
+
+    fn erase(&mut self, sector: usize) -> Result<(), Self::Error> {
+        if sector >= SECTOR_COUNT {
+            return Err(RamError::OutOfBounds);
+        }
+        if let Err(e) = self.step() {
+            self.sectors[sector][SECTOR_SIZE / 2..].fill(ERASED);
+            return Err(e);
+        }
+        self.sectors[sector] = [ERASED; SECTOR_SIZE];
+        self.erase_counts[sector] += 1;
+        Ok(())
+    }
+
+    fn program(&mut self, offset: usize, data: &[u8]) -> Result<(), Self::Error> {
+        Self::check_bounds(offset, data.len())?;
+        for (i, &b) in data.iter().enumerate() {
+            self.step()?;
+            let offset = offset + i;
+            self.sectors[offset / SECTOR_SIZE][offset % SECTOR_SIZE] &= b;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn programming_only_clears_bits() {
+        let mut storage = RamStorage::<16, 2>::default();
+        storage.program(18, &[0b1010_1010]).unwrap();
+        storage.program(18, &[0b0110_0110]).unwrap();
+        let mut buf = [0; 3];
+        storage.read(17, &mut buf).unwrap();
+        assert_eq!(buf, [ERASED, 0b0010_0010, ERASED]);
+        storage.erase(1).unwrap();
+        storage.read(17, &mut buf).unwrap();
+        assert_eq!(buf, [ERASED; 3]);
+        assert_eq!(storage.erase_count(1), 1);
+        assert_eq!(storage.read(30, &mut buf), Err(RamError::OutOfBounds));
+    }
+
+    #[test]
+    fn power_cut_stops_programming() {
+        let mut storage = RamStorage::<16, 1>::default();
+        storage.cut_power_after(Some(2));
+        assert_eq!(storage.program(0, &[0, 0, 0]), Err(RamError::PowerCut));
+        let mut buf = [0xaa; 3];
+        storage.read(0, &mut buf).unwrap();
+        assert_eq!(buf, [0, 0, ERASED]);
+        storage.cut_power_after(None);
+        storage.erase(0).unwrap();
+    }
+
+    #[test]
+    fn power_cut_leaves_a_sector_partly_erased() {
+        let mut storage = RamStorage::<16, 1>::default();
+        storage.program(0, &[0; 16]).unwrap();
+        storage.cut_power_after(Some(0));
+        assert_eq!(storage.erase(0), Err(RamError::PowerCut));
+        let mut buf = [0xaa; 16];
+        storage.read(0, &mut buf).unwrap();
+        assert_eq!(buf[..8], [0; 8]);
+        assert_eq!(buf[8..], [ERASED; 8]);
+        assert_eq!(storage.erase_count(0), 0);
+    }
+}
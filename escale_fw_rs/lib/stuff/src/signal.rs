@@ -1,5 +1,243 @@
 use num_traits::{float::FloatCore, Num, NumCast};
 
+use crate::simple_ring::SimpleRing;
+
+/// A filter that derives a single value from a window of samples.
+///
+/// The window is ordered from the oldest to the newest sample.
+pub trait WindowFilter<T, R> {
+    fn apply<Iter>(&self, window: Iter) -> Option<R>
+    where
+        Iter: DoubleEndedIterator<Item = T>;
+}
+
+/// Boxcar (moving average) filter, see [`mean`].
+#[derive(Default, Clone, Copy, Debug)]
+pub struct Mean;
+
+impl<T, R> WindowFilter<T, R> for Mean
+where
+    T: Num + NumCast,
+    R: FloatCore,
+{
+    fn apply<Iter>(&self, window: Iter) -> Option<R>
+    where
+        Iter: DoubleEndedIterator<Item = T>,
+    {
+        mean(window)
+    }
+}
+
+/// Finite impulse response filter with `N` taps (i.e. of order `N - 1`).
+///
+/// The taps are ordered as the `b` vector produced by `filter/fir.m`,
+/// so `taps[0]` applies to the newest sample. The taps may be either
+/// floating point or integer (fixed point) numbers. In both cases
+/// the accumulator has the type of the taps and the output is normalized
+/// by the sum of the taps, so the DC gain is exactly 1.
+#[derive(Clone, Debug)]
+pub struct Fir<C, const N: usize> {
+    taps: [C; N],
+    gain: C,
+}
+
+impl<C, const N: usize> Fir<C, N>
+where
+    C: Num + Copy,
+{
+    pub fn new(taps: [C; N]) -> Self {
+        let gain = taps.iter().fold(C::zero(), |sum, &c| sum + c);
+        assert!(gain != C::zero(), "the sum of the taps must not be zero");
+        Self { taps, gain }
+    }
+
+    pub fn taps(&self) -> &[C; N] {
+        &self.taps
+    }
+}
+
+impl<T, C, R, const N: usize> WindowFilter<T, R> for Fir<C, N>
+where
+    T: NumCast,
+    C: Num + NumCast + Copy,
+    R: FloatCore,
+{
+    /// Returns `None` if the window is shorter than the number of taps.
+    /// If the window is longer, only the newest `N` samples are used.
+    fn apply<Iter>(&self, window: Iter) -> Option<R>
+    where
+        Iter: DoubleEndedIterator<Item = T>,
+    {
+        const E_SAMPLE_SIZE: &str = "a sample should fit into the numeric type of the taps";
+        const E_ACC_SIZE: &str = "the accumulator should fit into the output numeric type";
+
+        let (count, acc) =
+            window
+                .rev()
+                .zip(self.taps.iter())
+                .fold((0usize, C::zero()), |state, (x, &c)| {
+                    let (count, acc) = state;
+                    (count + 1, acc + c * C::from(x).expect(E_SAMPLE_SIZE))
+                });
+        if count == N {
+            Some(R::from(acc).expect(E_ACC_SIZE) / R::from(self.gain).expect(E_ACC_SIZE))
+        } else {
+            None
+        }
+    }
+}
+
+/// A filter that processes samples one by one.
+pub trait SampleFilter<T> {
+    fn apply(&mut self, x: T) -> T;
+    fn reset(&mut self);
+}
+
+/// A filter that passes the samples through unchanged.
+#[derive(Default, Clone, Copy, Debug)]
+pub struct Passthrough;
+
+impl<T> SampleFilter<T> for Passthrough {
+    fn apply(&mut self, x: T) -> T {
+        x
+    }
+
+    fn reset(&mut self) {}
+}
+
+/// Running median over the last `N` samples.
+///
+/// The window is filled with the first sample after creation or reset.
+/// For an even `N` the upper median is used.
+#[derive(Debug)]
+pub struct MedianFilter<T, const N: usize>
+where
+    // Require N ≥ 1
+    [(); N - 1]:,
+{
+    ring: SimpleRing<T, N>,
+    is_initialized: bool,
+}
+
+impl<T: Copy + Default, const N: usize> Default for MedianFilter<T, N>
+where
+    [(); N - 1]:,
+{
+    fn default() -> Self {
+        Self {
+            ring: Default::default(),
+            is_initialized: false,
+        }
+    }
+}
+
+impl<T: Copy + PartialOrd, const N: usize> SampleFilter<T> for MedianFilter<T, N>
+where
+    [(); N - 1]:,
+{
+    fn apply(&mut self, x: T) -> T {
+        push_initializing(&mut self.ring, &mut self.is_initialized, x);
+        median(self.ring.data)
+    }
+
+    fn reset(&mut self) {
+        self.is_initialized = false;
+    }
+}
+
+/// Hampel filter over the last `N` samples.
+///
+/// A sample that deviates from the running median by more than `n_sigmas`
+/// standard deviations (estimated as 1.4826 × MAD) and more than
+/// `min_deviation` is replaced with the median. Other samples are passed
+/// through unchanged. `min_deviation` keeps the filter from rejecting
+/// ordinary noise when the window is (nearly) constant and MAD is zero.
+///
+/// The window is filled with the first sample after creation or reset.
+#[derive(Debug)]
+pub struct HampelFilter<T, const N: usize>
+where
+    [(); N - 1]:,
+{
+    ring: SimpleRing<T, N>,
+    is_initialized: bool,
+    n_sigmas: f32,
+    min_deviation: T,
+}
+
+impl<T: Copy + Default, const N: usize> HampelFilter<T, N>
+where
+    [(); N - 1]:,
+{
+    pub fn new(n_sigmas: f32, min_deviation: T) -> Self {
+        assert!(n_sigmas >= 0.0);
+        Self {
+            ring: Default::default(),
+            is_initialized: false,
+            n_sigmas,
+            min_deviation,
+        }
+    }
+}
+
+impl<T: Copy + PartialOrd + Num + NumCast, const N: usize> SampleFilter<T> for HampelFilter<T, N>
+where
+    [(); N - 1]:,
+{
+    fn apply(&mut self, x: T) -> T {
+        /// The scale factor relating MAD to the standard deviation
+        /// of normally distributed data.
+        const K_MAD: f32 = 1.4826;
+        const E_DEV_SIZE: &str = "a deviation should fit into f32";
+
+        push_initializing(&mut self.ring, &mut self.is_initialized, x);
+        let med = median(self.ring.data);
+        let mad = median(self.ring.data.map(|x| abs_diff(x, med)));
+        let dev = abs_diff(x, med);
+        let max_dev = self.n_sigmas * K_MAD * mad.to_f32().expect(E_DEV_SIZE);
+        if dev > self.min_deviation && dev.to_f32().expect(E_DEV_SIZE) > max_dev {
+            med
+        } else {
+            x
+        }
+    }
+
+    fn reset(&mut self) {
+        self.is_initialized = false;
+    }
+}
+
+fn push_initializing<T: Copy, const N: usize>(
+    ring: &mut SimpleRing<T, N>,
+    is_initialized: &mut bool,
+    x: T,
+) where
+    [(); N - 1]:,
+{
+    if *is_initialized {
+        ring.push(x);
+    } else {
+        ring.reset(x);
+        *is_initialized = true;
+    }
+}
+
+/// The upper median of the values.
+fn median<T: Copy + PartialOrd, const N: usize>(mut values: [T; N]) -> T {
+    const E_COMPARABLE: &str = "the values must be comparable";
+    let (_, &mut m, _) =
+        values.select_nth_unstable_by(N / 2, |a, b| a.partial_cmp(b).expect(E_COMPARABLE));
+    m
+}
+
+fn abs_diff<T: Num + PartialOrd>(a: T, b: T) -> T {
+    if a > b {
+        a - b
+    } else {
+        b - a
+    }
+}
+
 // pub fn mean<N, R>(values: &[N]) -> Option<R>
 pub fn mean<Iter, N, R>(iter: Iter) -> Option<R>
 where
@@ -36,4 +274,119 @@ mod tests {
         let xs: [i32; 0] = [];
         assert_eq!(mean::<_, _, f32>(xs.iter().copied()), None);
     }
+
+    /// `filter/fir.m` with `f_s = 20`, `f1 = 1e-3`, `f2 = 1.8`, `order = 20`.
+    const TAPS_F32: [f32; 21] = [
+        0.003900, 0.008932, 0.016286, 0.025921, 0.037441, 0.050100, 0.062865, 0.074539, 0.083930,
+        0.090021, 0.092132, 0.090021, 0.083930, 0.074539, 0.062865, 0.050100, 0.037441, 0.025921,
+        0.016286, 0.008932, 0.003900,
+    ];
+
+    /// Same as `TAPS_F32`, but scaled by 2^16 and rounded.
+    const TAPS_Q16: [i64; 21] = [
+        256, 585, 1067, 1699, 2454, 3283, 4120, 4885, 5500, 5900, 6038, 5900, 5500, 4885, 4120,
+        3283, 2454, 1699, 1067, 585, 256,
+    ];
+
+    fn step<const N: usize>(before: usize, height: i32) -> [i32; N] {
+        let mut xs = [0; N];
+        xs[before..].fill(height);
+        xs
+    }
+
+    /// A noisy constant signal with single-sample spikes at 7 and 13.
+    const SPIKY: [i32; 20] = [
+        100, 101, 99, 100, 102, 98, 100, 5000, 101, 99, 100, 100, 99, -3000, 101, 100, 102, 99,
+        100, 101,
+    ];
+
+    #[test]
+    fn median_rejects_spikes() {
+        let mut f = MedianFilter::<i32, 5>::default();
+        for x in SPIKY {
+            let y = f.apply(x);
+            assert!((98..=102).contains(&y), "{y}");
+        }
+    }
+
+    #[test]
+    fn median_follows_step_with_delay() {
+        let mut f = MedianFilter::<i32, 5>::default();
+        assert_eq!(f.apply(0), 0);
+        assert_eq!(f.apply(0), 0);
+        assert_eq!(f.apply(10), 0);
+        assert_eq!(f.apply(10), 0);
+        assert_eq!(f.apply(10), 10);
+        f.reset();
+        assert_eq!(f.apply(-5), -5);
+    }
+
+    #[test]
+    fn hampel_rejects_spikes_and_keeps_other_samples() {
+        let mut f = HampelFilter::<i32, 5>::new(3.0, 5);
+        for (i, x) in SPIKY.into_iter().enumerate() {
+            let y = f.apply(x);
+            if i == 7 || i == 13 {
+                assert!((98..=102).contains(&y), "{y}");
+            } else {
+                assert_eq!(y, x);
+            }
+        }
+    }
+
+    #[test]
+    fn hampel_follows_step_with_delay() {
+        let mut f = HampelFilter::<f32, 5>::new(3.0, 0.5);
+        let ys = [0.0, 0.1, -0.1, 10.0, 10.1, 9.9, 10.0].map(|x| f.apply(x));
+        assert_eq!(ys[3], 0.0);
+        assert_eq!(ys[4], 0.1);
+        assert_eq!(ys[5..], [9.9, 10.0]);
+    }
+
+    #[test]
+    fn fir_dc_gain_is_one() {
+        let xs = [1000; 21];
+        let y: f32 = Fir::new(TAPS_F32).apply(xs.iter().copied()).unwrap();
+        assert!((y - 1000.0).abs() < 1e-3);
+
+        let xs = [-8_000_000; 21];
+        let y: f64 = Fir::new(TAPS_Q16).apply(xs.iter().copied()).unwrap();
+        assert_eq!(y, -8_000_000.0);
+    }
+
+    #[test]
+    fn fir_step_response_is_monotonic_and_settles() {
+        let fir = Fir::new(TAPS_Q16);
+        let mut prev = 0.0;
+        for i in 0..=21 {
+            let xs = step::<21>(21 - i, 1000);
+            let y: f64 = fir.apply(xs.iter().copied()).unwrap();
+            assert!(y >= prev);
+            prev = y;
+        }
+        assert_eq!(prev, 1000.0);
+
+        // The taps are symmetric, so the response crosses ½ at the middle tap.
+        let xs = step::<21>(11, 1000);
+        let y: f64 = fir.apply(xs.iter().copied()).unwrap();
+        assert!(y < 500.0);
+        let xs = step::<21>(10, 1000);
+        let y: f64 = fir.apply(xs.iter().copied()).unwrap();
+        assert!(y > 500.0);
+    }
+
+    #[test]
+    fn fir_uses_newest_samples() {
+        let fir = Fir::new([1, 2, 3]);
+        let xs = [100, 1, 10, 100];
+        let y: f32 = fir.apply(xs.iter().copied()).unwrap();
+        assert_eq!(y, (1.0 * 100.0 + 2.0 * 10.0 + 3.0 * 1.0) / 6.0);
+    }
+
+    #[test]
+    fn fir_of_short_window_is_none() {
+        let fir = Fir::new([1, 2, 3]);
+        let xs = [1, 2];
+        assert_eq!(fir.apply::<_>(xs.iter().copied()), None::<f32>);
+    }
 }
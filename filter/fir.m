@@ -35,6 +35,11 @@ function b = design_fir(f_s, f1, f2, order)
   % printf("%f, ", b);
   % printf("]\n");
 
+  % Rust table for `stuff::signal::Fir` (Q16 fixed point)
+  printf("pub const TAPS: [i64; %d] = [", length(b));
+  printf("%d, ", round(b * 2^16));
+  printf("];\n");
+
   printf("\n");
 
   % figure();
@@ -46,7 +51,8 @@ function b = design_fir(f_s, f1, f2, order)
   plot(y, "DisplayName", name);
 endfunction
 
-f_s = 320;
+% f_s = 320;
+f_s = 20; % NAU7802 at 20 SPS
 % f1 = 0.25;
 % band_transition_width_oct = 2;
 
@@ -54,9 +60,10 @@ hold on;
 for f1 = 1e-3
   % for band_transition_width_oct = 2
   %   f2 = f1 * 2^(band_transition_width_oct - 1);
-  for f2 = 1.8
+  for f2 = [3.0, 1.8, 1.0] % Filter strength: low, medium, high
     % for k_order = 1.5
-    for order = 320
+    % for order = 320
+    for order = 20
       design_fir(f_s, f1, f2, order);
     endfor
   endfor